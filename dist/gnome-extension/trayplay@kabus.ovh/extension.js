@@ -0,0 +1,76 @@
+import Gio from 'gi://Gio';
+import GLib from 'gi://GLib';
+import Shell from 'gi://Shell';
+import {Extension} from 'resource:///org/gnome/shell/extensions/extension.js';
+
+// Installed extensions can't be templated like the KWin script, tests in src/dbus.rs keep these in sync
+const SERVICE = 'ovh.kabus.TrayPlay';
+const OBJECT_PATH = '/ovh/kabus/TrayPlay';
+const INTERFACE = 'ovh.kabus.TrayPlay.ActiveWindowManager';
+const METHOD = 'SetActiveWindow';
+
+export default class TrayPlayExtension extends Extension {
+    enable() {
+        this._window = null;
+        this._fullscreenChangedId = null;
+        this._focusChangedId = global.display.connect('notify::focus-window', () => this._focusChanged());
+        this._focusChanged();
+    }
+
+    disable() {
+        global.display.disconnect(this._focusChangedId);
+        this._focusChangedId = null;
+        this._forgetWindow();
+    }
+
+    _forgetWindow() {
+        if (this._window != null) {
+            this._window.disconnect(this._fullscreenChangedId);
+        }
+        this._window = null;
+        this._fullscreenChangedId = null;
+    }
+
+    _focusChanged() {
+        this._forgetWindow();
+
+        const window = global.display.focus_window;
+        if (window == null) {
+            return;
+        }
+
+        this._window = window;
+        this._fullscreenChangedId = window.connect('notify::fullscreen', () => this._send(window));
+        this._send(window);
+    }
+
+    _send(window) {
+        const app = Shell.WindowTracker.get_default().get_window_app(window);
+        const desktopFile = app != null && app.get_id() != null ? app.get_id().replace(/\.desktop$/, '') : '';
+        const geometry = window.get_frame_rect();
+
+        Gio.DBus.session.call(
+            SERVICE,
+            OBJECT_PATH,
+            INTERFACE,
+            METHOD,
+            new GLib.Variant('(ssbisiiiis)', [
+                desktopFile,
+                window.get_title() ?? '',
+                window.is_fullscreen(),
+                window.get_pid(),
+                window.get_wm_class() ?? '',
+                geometry.x,
+                geometry.y,
+                geometry.width,
+                geometry.height,
+                '',
+            ]),
+            null,
+            Gio.DBusCallFlags.NO_AUTO_START,
+            -1,
+            null,
+            null
+        );
+    }
+}
@@ -1,17 +1,34 @@
+// Placeholders are filled in by TrayPlay before loading the script
+const SERVICE = "%SERVICE%";
+const OBJECT_PATH = "%OBJECT_PATH%";
+const INTERFACE = "%INTERFACE%";
+const METHOD = "%METHOD%";
+
 var active_window = null;
 
+function setActiveWindow(window) {
+    callDBus(
+        SERVICE,
+        OBJECT_PATH,
+        INTERFACE,
+        METHOD,
+        window.desktopFileName,
+        window.caption,
+        window.fullScreen,
+        window.pid,
+        window.resourceClass,
+        // callDBus sends whole numbers as int32 and the rest as double, so always round
+        Math.round(window.frameGeometry.x),
+        Math.round(window.frameGeometry.y),
+        Math.round(window.frameGeometry.width),
+        Math.round(window.frameGeometry.height),
+        window.output ? window.output.name : "",
+    );
+}
+
 function send(window) {
     if (window.active) {
-        callDBus(
-            "ovh.kabus.trayplay",
-            "/ovh/kabus/trayplay",
-            "ovh.kabus.trayplay.ActiveWindowManager",
-            "SetActiveWindow",
-            window.desktopFileName,
-            window.caption,
-            window.fullScreen,
-            window.pid,
-        );
+        setActiveWindow(window);
         if (active_window != null) {
             active_window.fullScreenChanged.disconnect(fullScreenChanged);
         }
@@ -21,16 +38,7 @@ function send(window) {
 }
 
 function fullScreenChanged() {
-    callDBus(
-        "ovh.kabus.trayplay",
-        "/ovh/kabus/trayplay",
-        "ovh.kabus.trayplay.ActiveWindowManager",
-        "SetActiveWindow",
-        active_window.desktopFileName,
-        active_window.caption,
-        active_window.fullScreen,
-        active_window.pid,
-    );
+    setActiveWindow(active_window);
 }
 
 workspace.windowActivated.connect(send);
@@ -1,87 +1,471 @@
 use std::{
-    path::{Path, PathBuf},
+    collections::{HashMap, VecDeque},
+    path::PathBuf,
     sync::Arc,
+    time::{Duration, Instant},
 };
 
-use log::info;
+use log::{info, warn};
+use regex::Regex;
+use serde::Serialize;
 use tokio::{
     fs,
     sync::{RwLock, mpsc},
 };
-use zbus::interface;
+use zbus::{Connection, interface};
 
-use crate::utils;
+use crate::{
+    config::{AppAliasRule, Config, ReplayAttribution},
+    dbus,
+    desktop_entries::DesktopEntries,
+    game::{self, GameEnvironment},
+};
+
+/// Position and size of a window in the global compositor space. KWin uses logical
+/// pixels, so these can be fractional with scaling.
+#[derive(Serialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct WindowGeometry {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+/// Fullscreen window that saved replays are attributed to.
+#[derive(Debug, Clone)]
+pub struct ActiveWindow {
+    pub app_name: String,
+    pub desktop_file: String,
+    pub resource_class: String,
+    pub title: String,
+    pub fullscreen: bool,
+    pub geometry: WindowGeometry,
+    /// Name of the output (e.g. `DP-1`) the window is on
+    pub output: String,
+    pub pid: i32,
+    pub exe: Option<PathBuf>,
+    /// Set for games started by Steam, including Proton ones
+    pub steam_app_id: Option<u32>,
+}
+
+impl ActiveWindow {
+    pub fn is_game(&self) -> bool {
+        self.steam_app_id.is_some()
+    }
+}
+
+impl Default for ActiveWindow {
+    fn default() -> Self {
+        Self {
+            app_name: "unknown".to_string(),
+            desktop_file: String::new(),
+            resource_class: String::new(),
+            title: String::new(),
+            fullscreen: false,
+            geometry: WindowGeometry::default(),
+            output: String::new(),
+            pid: 0,
+            exe: None,
+            steam_app_id: None,
+        }
+    }
+}
+
+/// Recent changes of the active window, so a replay can be attributed to a game the user
+/// has just alt-tabbed out of.
+#[derive(Debug, Default)]
+pub struct WindowHistory {
+    /// Windows in the order they became active, oldest first
+    changes: VecDeque<(Instant, ActiveWindow)>,
+}
+
+impl WindowHistory {
+    pub fn current(&self) -> ActiveWindow {
+        self.changes
+            .back()
+            .map(|(_, window)| window.clone())
+            .unwrap_or_default()
+    }
+
+    /// Forgets changes that can't be part of a replay shorter than `keep` anymore.
+    pub fn push(&mut self, window: ActiveWindow, keep: Duration) {
+        let now = Instant::now();
+        self.changes.push_back((now, window));
+
+        // The last change before the cutoff tells what was active at the cutoff itself
+        while self
+            .changes
+            .get(1)
+            .is_some_and(|(since, _)| now.duration_since(*since) > keep)
+        {
+            self.changes.pop_front();
+        }
+    }
+
+    /// Window a replay covering `length` until now should be saved as. With
+    /// [`ReplayAttribution::LongestFullscreen`], an app has to be fullscreen for most of the
+    /// replay, otherwise it's the current window.
+    pub fn attribute(&self, policy: ReplayAttribution, length: Duration) -> ActiveWindow {
+        let now = Instant::now();
+        let window_start = now.checked_sub(length).unwrap_or(now);
 
-struct ActiveWindowManager {
-    tx: mpsc::Sender<(String, String, bool, i32)>,
+        // How long each window was active inside the replay, newest first
+        let spans = self
+            .changes
+            .iter()
+            .enumerate()
+            .rev()
+            .map(|(index, (since, window))| {
+                let until = self.changes.get(index + 1).map_or(now, |(until, _)| *until);
+                (window, *since, until)
+            })
+            .take_while(|(_, _, until)| *until > window_start)
+            .map(|(window, since, until)| (window, until.duration_since(since.max(window_start))))
+            .collect::<Vec<_>>();
+        let fullscreen_spans = spans.iter().filter(|(window, _)| window.fullscreen);
+
+        let attributed = match policy {
+            ReplayAttribution::Current => None,
+            ReplayAttribution::LastFullscreen => fullscreen_spans.map(|(window, _)| *window).next(),
+            ReplayAttribution::LongestFullscreen => {
+                let mut totals: HashMap<&str, (Duration, &ActiveWindow)> = HashMap::new();
+                for (window, active_for) in fullscreen_spans {
+                    // Keep the newest window of every app, as it has the most recent title
+                    totals
+                        .entry(&window.app_name)
+                        .or_insert((Duration::ZERO, window))
+                        .0 += *active_for;
+                }
+
+                // Only the part of the replay the history knows about counts
+                let covered = spans
+                    .iter()
+                    .map(|(_, active_for)| *active_for)
+                    .sum::<Duration>();
+                totals
+                    .into_values()
+                    .max_by_key(|(active_for, _)| *active_for)
+                    .filter(|(active_for, _)| *active_for > covered / 2)
+                    .map(|(_, window)| window)
+            }
+        };
+
+        attributed.cloned().unwrap_or_else(|| self.current())
+    }
+}
+
+pub struct ActiveWindowManager {
+    tx: mpsc::Sender<ActiveWindow>,
+}
+
+impl ActiveWindowManager {
+    pub fn new(tx: mpsc::Sender<ActiveWindow>) -> Self {
+        Self { tx }
+    }
 }
 
 #[interface(name = "ovh.kabus.TrayPlay.ActiveWindowManager")]
 impl ActiveWindowManager {
-    async fn set_active_window(&self, desktop_file: &str, title: &str, fullscreen: bool, pid: i32) {
+    /// Geometry is sent as whole numbers, as KWin's `callDBus` turns those into int32.
+    #[allow(clippy::too_many_arguments)]
+    async fn set_active_window(
+        &self,
+        desktop_file: &str,
+        title: &str,
+        fullscreen: bool,
+        pid: i32,
+        resource_class: &str,
+        x: i32,
+        y: i32,
+        width: i32,
+        height: i32,
+        output: &str,
+    ) {
         self.tx
-            .send((desktop_file.to_string(), title.to_string(), fullscreen, pid))
+            .send(ActiveWindow {
+                app_name: String::new(),
+                desktop_file: desktop_file.to_string(),
+                resource_class: resource_class.to_string(),
+                title: title.to_string(),
+                fullscreen,
+                geometry: WindowGeometry {
+                    x: x as f64,
+                    y: y as f64,
+                    width: width as f64,
+                    height: height as f64,
+                },
+                output: output.to_string(),
+                pid,
+                exe: None,
+                steam_app_id: None,
+            })
             .await
             .unwrap();
     }
 }
 
 pub async fn setup_active_window_manager(
-    app_name: Arc<RwLock<String>>,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let (app_name_tx, mut app_name_rx) = mpsc::channel(8);
+    window_history: Arc<RwLock<WindowHistory>>,
+    config: Arc<RwLock<Config>>,
+) -> Result<(Connection, mpsc::Sender<ActiveWindow>), Box<dyn std::error::Error>> {
+    let (window_tx, mut window_rx) = mpsc::channel(8);
 
-    let active_window_manager = ActiveWindowManager { tx: app_name_tx };
+    let active_window_manager = ActiveWindowManager::new(window_tx.clone());
 
-    let _conn = zbus::connection::Builder::session()?
-        .name("ovh.kabus.TrayPlay")?
-        .serve_at("/ovh/kabus/TrayPlay", active_window_manager)?
+    let conn = zbus::connection::Builder::session()?
+        .name(dbus::SERVICE_NAME)?
+        .serve_at(dbus::OBJECT_PATH, active_window_manager)?
         .build()
         .await?;
 
+    let _conn = conn.clone();
     tokio::spawn(async move {
         // Move connection inside tokio task so it doesn't get dropped immediately
         let _conn = _conn;
+        let mut desktop_entries = DesktopEntries::from_env();
+        let mut alias_rules = AliasRules::default();
 
         loop {
-            if let Some((desktop_file, title, fullscreen, pid)) = app_name_rx.recv().await {
-                if fullscreen {
-                    let mut app_name_new =
-                        utils::get_app_name(&desktop_file).unwrap().unwrap_or(title);
-                    if app_name_new.len() > 100 {
-                        // app name too long - let's find executable name
-                        if let Ok(path) = fs::read_link(format!("/proc/{}/exe", pid)).await {
-                            app_name_new = path.file_name().unwrap().display().to_string();
-                            if app_name_new == "wine-preloader" {
-                                // try to find wine exe name
-                                if let Ok(cmdline) =
-                                    fs::read_to_string(format!("/proc/{}/cmdline", pid)).await
-                                {
-                                    app_name_new = cmdline
-                                        .split('\0')
-                                        .next()
-                                        .unwrap()
-                                        .split('\\')
-                                        .last()
-                                        .unwrap()
-                                        .replace(".exe", "");
-                                    // TODO: Structure this part better
-                                }
-                            }
-                        } else {
-                            // process died?
-                            app_name_new = "unknown".to_string();
+            if let Some(mut window) = window_rx.recv().await {
+                let keep =
+                    Duration::from_secs(config.read().await.replay_duration_secs.max(0) as u64);
+
+                if window.fullscreen {
+                    let pid = window.pid;
+                    window.exe = fs::read_link(format!("/proc/{}/exe", pid)).await.ok();
+                    let environment = GameEnvironment::read(pid).await;
+                    window.steam_app_id = environment.steam_app_id;
+
+                    let config_values = config.read().await;
+                    let steam_library_folders = config_values.steam_library_folders.clone();
+                    alias_rules.update(&config_values.app_alias_rules);
+                    let rule_name = alias_rules.find(&window).map(str::to_string);
+                    let aliases = config_values.app_aliases.clone();
+                    drop(config_values);
+
+                    let app_name_new = match rule_name {
+                        Some(rule_name) => rule_name,
+                        None => {
+                            let app_name = detect_app_name(
+                                &window,
+                                &environment,
+                                &steam_library_folders,
+                                &mut desktop_entries,
+                            )
+                            .await;
+                            aliases.get(&app_name).cloned().unwrap_or(app_name)
                         }
-                    }
+                    };
                     info!("Current app is now {}", app_name_new);
-                    *app_name.write().await = app_name_new;
-                } else if *app_name.read().await != "unknown" {
+                    window.app_name = app_name_new;
+                    window_history.write().await.push(window, keep);
+                } else if window_history.read().await.current().fullscreen {
                     info!("Current app is unknown");
-                    *app_name.write().await = "unknown".to_string();
+                    window_history
+                        .write()
+                        .await
+                        .push(ActiveWindow::default(), keep);
                 }
             }
         }
     });
 
-    Ok(())
+    Ok((conn, window_tx))
+}
+
+async fn detect_app_name(
+    window: &ActiveWindow,
+    environment: &GameEnvironment,
+    steam_library_folders: &[PathBuf],
+    desktop_entries: &mut DesktopEntries,
+) -> String {
+    let game_name = game::game_name(window, environment, steam_library_folders).await;
+
+    let app_name = match game_name {
+        Some(game_name) => game_name,
+        None => desktop_entries
+            .app_name(&window.desktop_file)
+            .unwrap_or(window.title.clone()),
+    };
+
+    if app_name.len() > 100 {
+        // app name too long - let's find executable name
+        match &window.exe {
+            Some(path) => path.file_name().unwrap().display().to_string(),
+            // process died?
+            None => "unknown".to_string(),
+        }
+    } else {
+        app_name
+    }
+}
+
+/// [`AppAliasRule`]s with their title patterns compiled, so that only happens when the
+/// rules change.
+#[derive(Debug, Default)]
+struct AliasRules {
+    /// Rules as in the config, to notice changes
+    source: Vec<AppAliasRule>,
+    /// Rules with a valid title pattern, in config order
+    rules: Vec<(AppAliasRule, Option<Regex>)>,
+}
+
+impl AliasRules {
+    /// Rules with an invalid title pattern are reported once and never match.
+    fn update(&mut self, rules: &[AppAliasRule]) {
+        if self.source == rules {
+            return;
+        }
+
+        self.source = rules.to_vec();
+        self.rules = rules
+            .iter()
+            .filter_map(|rule| match rule.title.as_deref().map(Regex::new) {
+                None => Some((rule.clone(), None)),
+                Some(Ok(regex)) => Some((rule.clone(), Some(regex))),
+                Some(Err(err)) => {
+                    warn!(
+                        "Invalid title pattern in app alias rule {}, it's ignored: {}",
+                        rule.name, err
+                    );
+                    None
+                }
+            })
+            .collect();
+    }
+
+    /// Name given by the first matching rule.
+    fn find(&self, window: &ActiveWindow) -> Option<&str> {
+        self.rules
+            .iter()
+            .find(|(rule, title)| rule_matches(rule, title.as_ref(), window))
+            .map(|(rule, _)| rule.name.as_str())
+    }
+}
+
+/// A rule without any condition never matches, so it can't swallow every window.
+fn rule_matches(rule: &AppAliasRule, title: Option<&Regex>, window: &ActiveWindow) -> bool {
+    if rule.desktop_file.is_none()
+        && rule.exe.is_none()
+        && rule.title.is_none()
+        && rule.steam_app_id.is_none()
+    {
+        return false;
+    }
+
+    title.is_none_or(|title| title.is_match(&window.title))
+        && rule
+            .desktop_file
+            .as_ref()
+            .is_none_or(|desktop_file| *desktop_file == window.desktop_file)
+        && rule
+            .exe
+            .as_ref()
+            .is_none_or(|exe| window.exe.as_ref() == Some(exe))
+        && rule
+            .steam_app_id
+            .is_none_or(|app_id| window.steam_app_id == Some(app_id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn window(app_name: &str, fullscreen: bool) -> ActiveWindow {
+        ActiveWindow {
+            app_name: app_name.to_string(),
+            fullscreen,
+            ..Default::default()
+        }
+    }
+
+    /// History of windows that became active the given number of seconds ago
+    fn history(changes: &[(u64, ActiveWindow)]) -> WindowHistory {
+        let now = Instant::now();
+        WindowHistory {
+            changes: changes
+                .iter()
+                .map(|(ago, window)| (now - Duration::from_secs(*ago), window.clone()))
+                .collect(),
+        }
+    }
+
+    fn attribute(history: &WindowHistory, policy: ReplayAttribution) -> String {
+        history.attribute(policy, Duration::from_secs(180)).app_name
+    }
+
+    fn rule(name: &str, title: Option<&str>, steam_app_id: Option<u32>) -> AppAliasRule {
+        AppAliasRule {
+            name: name.to_string(),
+            desktop_file: None,
+            exe: None,
+            title: title.map(str::to_string),
+            steam_app_id,
+        }
+    }
+
+    #[test]
+    fn alias_rules() {
+        let mut rules = AliasRules::default();
+        rules.update(&[
+            rule("Broken", Some("(unclosed"), None),
+            rule("Empty", None, None),
+            rule("Editor", Some("^Level Editor"), None),
+            rule("Half-Life 2", None, Some(220)),
+        ]);
+
+        let mut window = window("hl2_linux", true);
+        assert_eq!(rules.find(&window), None);
+
+        window.steam_app_id = Some(220);
+        assert_eq!(rules.find(&window), Some("Half-Life 2"));
+
+        window.title = "Level Editor - map01".to_string();
+        assert_eq!(rules.find(&window), Some("Editor"));
+    }
+
+    #[test]
+    fn longest_fullscreen_needs_most_of_the_replay() {
+        // Fullscreen for 5 seconds, then windowed for the rest
+        let history = history(&[
+            (185, window("Browser", false)),
+            (180, window("Game", true)),
+            (175, window("Browser", false)),
+        ]);
+
+        assert_eq!(
+            attribute(&history, ReplayAttribution::LongestFullscreen),
+            "Browser"
+        );
+        assert_eq!(
+            attribute(&history, ReplayAttribution::LastFullscreen),
+            "Game"
+        );
+    }
+
+    #[test]
+    fn longest_fullscreen_across_alt_tabs() {
+        let history = history(&[
+            (200, window("Game", true)),
+            (100, window("Chat", false)),
+            (80, window("Game", true)),
+            (10, window("Chat", false)),
+        ]);
+
+        assert_eq!(
+            attribute(&history, ReplayAttribution::LongestFullscreen),
+            "Game"
+        );
+        assert_eq!(attribute(&history, ReplayAttribution::Current), "Chat");
+    }
+
+    #[test]
+    fn longest_fullscreen_picks_the_longer_game() {
+        let history = history(&[(180, window("Short", true)), (150, window("Long", true))]);
+
+        assert_eq!(
+            attribute(&history, ReplayAttribution::LongestFullscreen),
+            "Long"
+        );
+    }
 }
@@ -0,0 +1,1123 @@
+use std::{error::Error, sync::Arc};
+
+use log::{info, warn};
+use tokio::sync::{
+    RwLock,
+    mpsc::{self, error::TrySendError},
+    watch,
+};
+
+use crate::{
+    ActionEvent,
+    active_window::WindowHistory,
+    config::{Config, Shortcut},
+    container::RemuxLocks,
+    feedback::Feedback,
+    gsr::{self, GpuScreenRecorder},
+    recent::{RecentReplay, RecentReplays},
+    retention,
+    transcode::TranscodeJob,
+    tray::TrayHandle,
+    ui::Ui,
+    utils::{ask_path, open_file, show_in_folder},
+    window_source::ActiveWindowSource,
+};
+
+/// Dialogs and windows [`App`] opens.
+pub trait AppUi {
+    fn show_window(&self, id: &str);
+    fn copy_to_clipboard(&self, text: &str);
+    async fn show_info(&mut self, title: &str, text: &str);
+    async fn show_error(&mut self, title: &str, text: &str);
+}
+
+impl AppUi for Ui {
+    fn show_window(&self, id: &str) {
+        Ui::show_window(self, id);
+    }
+
+    fn copy_to_clipboard(&self, text: &str) {
+        Ui::copy_to_clipboard(self, text);
+    }
+
+    async fn show_info(&mut self, title: &str, text: &str) {
+        Ui::show_info(self, title, text).await;
+    }
+
+    async fn show_error(&mut self, title: &str, text: &str) {
+        Ui::show_error(self, title, text).await;
+    }
+}
+
+/// What [`App`] changes in the tray icon.
+pub trait AppTray {
+    async fn update(&self);
+    async fn set_status(&self, status: Option<String>);
+}
+
+impl AppTray for TrayHandle {
+    async fn update(&self) {
+        TrayHandle::update(self).await;
+    }
+
+    async fn set_status(&self, status: Option<String>) {
+        TrayHandle::set_status(self, status).await;
+    }
+}
+
+/// How [`App`] controls the replay recorder.
+pub trait Recorder {
+    fn is_running(&self) -> bool;
+    async fn start(&mut self) -> Result<(), gsr::Error>;
+    async fn stop(&mut self) -> Result<(), gsr::Error>;
+    async fn restart(&mut self) -> Result<(), gsr::Error>;
+    async fn save_replay(&mut self) -> Result<(), gsr::Error>;
+    async fn mark_moment(&self) -> Result<(), gsr::Error>;
+}
+
+impl Recorder for GpuScreenRecorder {
+    fn is_running(&self) -> bool {
+        GpuScreenRecorder::is_running(self)
+    }
+
+    async fn start(&mut self) -> Result<(), gsr::Error> {
+        GpuScreenRecorder::start(self).await
+    }
+
+    async fn stop(&mut self) -> Result<(), gsr::Error> {
+        GpuScreenRecorder::stop(self).await
+    }
+
+    async fn restart(&mut self) -> Result<(), gsr::Error> {
+        GpuScreenRecorder::restart(self).await
+    }
+
+    async fn save_replay(&mut self) -> Result<(), gsr::Error> {
+        GpuScreenRecorder::save_replay(self).await
+    }
+
+    async fn mark_moment(&self) -> Result<(), gsr::Error> {
+        GpuScreenRecorder::mark_moment(self).await
+    }
+}
+
+/// Everything the main loop needs to react to [`ActionEvent`]s.
+///
+/// Handlers return errors instead of propagating them out of `main`, so a single failed
+/// action (missing OSD service, recorder that won't restart) doesn't take the tray down.
+/// The UI, tray and recorder can be swapped for fakes in tests.
+pub struct App<'a, U = Ui, T = TrayHandle, R = GpuScreenRecorder> {
+    pub config: Arc<RwLock<Config>>,
+    pub ui: U,
+    pub recent_replays: Arc<RwLock<RecentReplays>>,
+    pub tray_handle: T,
+    pub gpu_screen_recorder: R,
+    pub window_history: Arc<RwLock<WindowHistory>>,
+    pub feedback: Feedback<'a>,
+    pub window_source: Option<Box<dyn ActiveWindowSource>>,
+    pub shortcuts_tx: watch::Sender<Vec<Shortcut>>,
+    pub configure_shortcuts_tx: mpsc::Sender<()>,
+    pub transcode_tx: mpsc::Sender<TranscodeJob>,
+    pub janitor_tx: mpsc::Sender<()>,
+    /// Replays still being tagged, trashing waits for them
+    pub remux_locks: RemuxLocks,
+}
+
+impl<'a, U: AppUi, T: AppTray, R: Recorder> App<'a, U, T, R> {
+    pub async fn handle_action(&mut self, action: ActionEvent) -> Result<(), Box<dyn Error>> {
+        match action {
+            ActionEvent::SaveReplay => {
+                info!(
+                    "Saving replay from {}",
+                    self.window_history.read().await.current().app_name
+                );
+                self.gpu_screen_recorder
+                    .save_replay()
+                    .await
+                    .map_err(|err| match err {
+                        gsr::Error::RecorderNotRunning => {
+                            "Replay recording is either turned off or has crashed.".into()
+                        }
+                        gsr::Error::LowDiskSpace(path, free_mb) => format!(
+                            "Replay was not saved, only {} MB of free space left in {}.",
+                            free_mb,
+                            path.display()
+                        ),
+                        err => format!("Failed to save replay: {}", err),
+                    })?;
+            }
+            ActionEvent::Quit => {
+                if let Some(window_source) = &mut self.window_source {
+                    window_source.stop().await;
+                }
+                match self.gpu_screen_recorder.stop().await {
+                    Ok(_) | Err(gsr::Error::RecorderNotRunning) => {}
+                    Err(err) => warn!("Failed to stop gpu-screen-recorder: {}", err),
+                }
+                std::process::exit(0);
+            }
+            ActionEvent::ChangeReplayPath => {
+                let mut config = self.config.write().await;
+                let directory = ask_path(true, &config.replay_directory)
+                    .await
+                    .map_err(|err| format!("Error when asking for replay directory: {}", err))?;
+
+                if let Some(directory) = directory {
+                    config.replay_directory = directory;
+                    config.save().await;
+                }
+            }
+            ActionEvent::ConfigSaved => {
+                let shortcuts = self.config.read().await.shortcuts.clone();
+                self.shortcuts_tx.send_if_modified(|current| {
+                    if *current != shortcuts {
+                        *current = shortcuts;
+                        true
+                    } else {
+                        false
+                    }
+                });
+                let _ = self.janitor_tx.try_send(());
+
+                if self.gpu_screen_recorder.is_running() {
+                    self.gpu_screen_recorder
+                        .restart()
+                        .await
+                        .map_err(describe_start_error)?;
+                }
+            }
+            ActionEvent::ToggleReplay => {
+                let recording_enabled = if self.gpu_screen_recorder.is_running() {
+                    self.gpu_screen_recorder.stop().await?;
+                    self.feedback.recording_stopped().await;
+                    false
+                } else {
+                    self.gpu_screen_recorder
+                        .start()
+                        .await
+                        .map_err(describe_start_error)?;
+                    self.feedback.recording_started().await;
+                    true
+                };
+
+                let mut config = self.config.write().await;
+                config.recording_enabled = recording_enabled;
+                config.save().await;
+                drop(config);
+
+                self.tray_handle.update().await;
+            }
+            ActionEvent::MarkMoment => {
+                self.gpu_screen_recorder
+                    .mark_moment()
+                    .await
+                    .map_err(|err| match err {
+                        gsr::Error::RecorderNotRunning => {
+                            "Cannot mark a moment while replay recording is turned off.".into()
+                        }
+                        err => format!("Failed to mark moment: {}", err),
+                    })?;
+                self.feedback.moment_marked().await;
+            }
+            ActionEvent::ReplaySaved(path, app_name) => {
+                retention::record_saved(&path);
+                self.feedback.replay_saved(&app_name, &path).await;
+
+                let config = self.config.read().await;
+                let limit = config.recent_replays_limit;
+                let auto_profiles = config
+                    .transcode_profiles
+                    .iter()
+                    .filter(|profile| profile.auto)
+                    .cloned()
+                    .collect::<Vec<_>>();
+                drop(config);
+
+                self.recent_replays
+                    .write()
+                    .await
+                    .push(RecentReplay::new(path.clone(), app_name.clone()), limit);
+                self.tray_handle.update().await;
+                // A cleanup already waiting is as good as a new one
+                let _ = self.janitor_tx.try_send(());
+
+                for profile in auto_profiles {
+                    self.queue_transcode(TranscodeJob {
+                        input: path.clone(),
+                        app_name: app_name.clone(),
+                        profile,
+                    })?;
+                }
+            }
+            ActionEvent::ApplyRetention => {
+                // A cleanup already waiting is as good as a new one
+                let _ = self.janitor_tx.try_send(());
+            }
+            ActionEvent::ToggleKeepReplay(path) => {
+                self.recent_replays.write().await.toggle_kept(&path);
+                self.tray_handle.update().await;
+            }
+            ActionEvent::Transcode(path, profile_name) => {
+                let profile = self
+                    .config
+                    .read()
+                    .await
+                    .transcode_profiles
+                    .iter()
+                    .find(|profile| profile.name == profile_name)
+                    .cloned()
+                    .ok_or_else(|| format!("Conversion profile {} doesn't exist", profile_name))?;
+                let app_name = self
+                    .recent_replays
+                    .read()
+                    .await
+                    .replays()
+                    .iter()
+                    .find(|replay| replay.path == path)
+                    .map(|replay| replay.app_name.clone())
+                    .unwrap_or_else(|| "unknown".to_string());
+
+                self.queue_transcode(TranscodeJob {
+                    input: path,
+                    app_name,
+                    profile,
+                })?;
+            }
+            ActionEvent::TranscodeProgress(status) => {
+                self.tray_handle.set_status(status).await;
+            }
+            ActionEvent::TranscodeFinished(path, app_name, profile_name) => {
+                retention::record_saved(&path);
+                self.feedback.replay_converted(&profile_name, &path).await;
+                let limit = self.config.read().await.recent_replays_limit;
+                self.recent_replays
+                    .write()
+                    .await
+                    .push(RecentReplay::new(path, app_name), limit);
+                self.tray_handle.update().await;
+            }
+            ActionEvent::OpenReplay(path) => {
+                open_file(&path)
+                    .await
+                    .map_err(|err| format!("Cannot open {}: {}", path.display(), err))?;
+            }
+            ActionEvent::ShowReplayInFolder(path) => {
+                show_in_folder(&path)
+                    .await
+                    .map_err(|err| format!("Cannot show {} in folder: {}", path.display(), err))?;
+            }
+            ActionEvent::CopyReplayPath(path) => {
+                self.ui.copy_to_clipboard(&path.display().to_string());
+            }
+            ActionEvent::TrashReplay(path) => {
+                // The remux would bring a freshly saved replay back
+                self.remux_locks.wait(&path).await;
+                // Takes the metadata and chapter files along
+                retention::trash_replay(&path)
+                    .await
+                    .map_err(|err| format!("Cannot move {} to trash: {}", path.display(), err))?;
+                self.recent_replays.write().await.remove(&path);
+                self.tray_handle.update().await;
+            }
+            ActionEvent::SetFramerate(framerate) => {
+                self.update_config(|config| config.framerate = framerate)
+                    .await;
+            }
+            ActionEvent::SetQuality(quality) => {
+                self.update_config(|config| config.quality = quality).await;
+            }
+            ActionEvent::SetReplayDuration(secs) => {
+                self.update_config(|config| config.replay_duration_secs = secs)
+                    .await;
+            }
+            ActionEvent::SetVideoSource(screen) => {
+                self.update_config(|config| config.screen = screen).await;
+            }
+            ActionEvent::ShortcutsStatusChanged => {
+                self.tray_handle.update().await;
+            }
+            ActionEvent::ConfigureShortcuts => {
+                // A request already waiting is as good as a new one
+                let _ = self.configure_shortcuts_tx.try_send(());
+            }
+            ActionEvent::ShowWindow(id) => {
+                self.ui.show_window(&id);
+            }
+            ActionEvent::ShowInfo(title, text) => {
+                self.ui.show_info(&title, &text).await;
+            }
+            ActionEvent::ShowError(title, text) => {
+                if !self.feedback.error(&text).await {
+                    self.ui.show_error(&title, &text).await;
+                }
+            }
+            other => {
+                warn!("Unhandled action event: {:?}", other)
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Never waits for room in the queue: the worker reports progress through the action
+    /// channel this loop drains, so blocking here could deadlock both.
+    fn queue_transcode(&self, job: TranscodeJob) -> Result<(), String> {
+        self.transcode_tx.try_send(job).map_err(|err| match err {
+            TrySendError::Full(job) => format!(
+                "Too many conversions are queued, {} wasn't converted to {}",
+                job.input.display(),
+                job.profile.name
+            ),
+            TrySendError::Closed(_) => "The conversion queue isn't running".to_string(),
+        })
+    }
+
+    /// Saving the config restarts the recorder through [`ActionEvent::ConfigSaved`].
+    async fn update_config(&self, update: impl FnOnce(&mut Config)) {
+        let mut config = self.config.write().await;
+        update(&mut config);
+        config.save().await;
+        drop(config);
+
+        self.tray_handle.update().await;
+    }
+}
+
+pub fn describe_start_error(err: gsr::Error) -> String {
+    match err {
+        gsr::Error::IoError(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            "gpu-screen-recorder is not installed!".to_string()
+        }
+        err => format!("Error while starting gpu-screen-recorder: {}", err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        cell::RefCell,
+        path::{Path, PathBuf},
+        rc::Rc,
+    };
+
+    use futures::future::LocalBoxFuture;
+    use tokio::sync::Mutex;
+
+    use super::*;
+    use crate::{
+        config::{Container, FeedbackBackend, FeedbackEvents, Quality, TranscodeProfile},
+        feedback::Osd,
+        utils,
+    };
+
+    /// What the fakes were asked to do, in order.
+    type Calls = Rc<RefCell<Vec<String>>>;
+
+    struct FakeUi(Calls);
+
+    impl AppUi for FakeUi {
+        fn show_window(&self, id: &str) {
+            self.0.borrow_mut().push(format!("window {}", id));
+        }
+
+        fn copy_to_clipboard(&self, text: &str) {
+            self.0.borrow_mut().push(format!("clipboard {}", text));
+        }
+
+        async fn show_info(&mut self, title: &str, text: &str) {
+            self.0
+                .borrow_mut()
+                .push(format!("info {}: {}", title, text));
+        }
+
+        async fn show_error(&mut self, title: &str, text: &str) {
+            self.0
+                .borrow_mut()
+                .push(format!("error {}: {}", title, text));
+        }
+    }
+
+    struct FakeTray(Calls);
+
+    impl AppTray for FakeTray {
+        async fn update(&self) {
+            self.0.borrow_mut().push("tray update".to_string());
+        }
+
+        async fn set_status(&self, status: Option<String>) {
+            self.0
+                .borrow_mut()
+                .push(format!("tray status {:?}", status));
+        }
+    }
+
+    /// Behaves like gpu-screen-recorder without recording anything.
+    struct FakeRecorder {
+        calls: Calls,
+        running: bool,
+        installed: bool,
+    }
+
+    impl Recorder for FakeRecorder {
+        fn is_running(&self) -> bool {
+            self.running
+        }
+
+        async fn start(&mut self) -> Result<(), gsr::Error> {
+            self.calls.borrow_mut().push("recorder start".to_string());
+            if !self.installed {
+                return Err(std::io::Error::from(std::io::ErrorKind::NotFound).into());
+            }
+            self.running = true;
+            Ok(())
+        }
+
+        async fn stop(&mut self) -> Result<(), gsr::Error> {
+            self.calls.borrow_mut().push("recorder stop".to_string());
+            if !self.running {
+                return Err(gsr::Error::RecorderNotRunning);
+            }
+            self.running = false;
+            Ok(())
+        }
+
+        async fn restart(&mut self) -> Result<(), gsr::Error> {
+            self.calls.borrow_mut().push("recorder restart".to_string());
+            if !self.installed {
+                return Err(std::io::Error::from(std::io::ErrorKind::NotFound).into());
+            }
+            self.running = true;
+            Ok(())
+        }
+
+        async fn save_replay(&mut self) -> Result<(), gsr::Error> {
+            self.calls.borrow_mut().push("recorder save".to_string());
+            if !self.running {
+                return Err(gsr::Error::RecorderNotRunning);
+            }
+            Ok(())
+        }
+
+        async fn mark_moment(&self) -> Result<(), gsr::Error> {
+            self.calls.borrow_mut().push("recorder mark".to_string());
+            if !self.running {
+                return Err(gsr::Error::RecorderNotRunning);
+            }
+            Ok(())
+        }
+    }
+
+    /// OSD or notification service, failing like a missing D-Bus service when not running.
+    struct FakeOsd {
+        calls: Calls,
+        name: &'static str,
+        running: bool,
+    }
+
+    impl Osd for FakeOsd {
+        fn show<'a>(
+            &'a self,
+            _icon: &'a str,
+            text: &'a str,
+            _actions: &'a [&'a str],
+        ) -> LocalBoxFuture<'a, Result<Option<u32>, Box<dyn std::error::Error>>> {
+            Box::pin(async move {
+                if !self.running {
+                    return Err(format!("{} is not running", self.name).into());
+                }
+                self.calls
+                    .borrow_mut()
+                    .push(format!("{} {}", self.name, text));
+                Ok(None)
+            })
+        }
+    }
+
+    /// Which fake services are there and what the recorder is doing.
+    struct Setup {
+        osd: bool,
+        notifications: bool,
+        recording: bool,
+        installed: bool,
+        events: FeedbackEvents,
+    }
+
+    impl Default for Setup {
+        fn default() -> Self {
+            Self {
+                osd: true,
+                notifications: true,
+                recording: true,
+                installed: true,
+                events: FeedbackEvents::default(),
+            }
+        }
+    }
+
+    struct Harness {
+        app: App<'static, FakeUi, FakeTray, FakeRecorder>,
+        calls: Calls,
+        action_rx: mpsc::Receiver<ActionEvent>,
+        configure_shortcuts_rx: mpsc::Receiver<()>,
+        transcode_rx: mpsc::Receiver<TranscodeJob>,
+        janitor_rx: mpsc::Receiver<()>,
+    }
+
+    impl Harness {
+        async fn new(setup: Setup) -> Self {
+            let calls = Calls::default();
+            let (action_tx, action_rx) = mpsc::channel(8);
+            let (configure_shortcuts_tx, configure_shortcuts_rx) = mpsc::channel(1);
+            let (transcode_tx, transcode_rx) = mpsc::channel(8);
+            let (janitor_tx, janitor_rx) = mpsc::channel(1);
+
+            let config = Config::load(action_tx).await;
+            let (shortcuts_tx, _) = watch::channel(config.shortcuts.clone());
+            let osd = |name, running| -> Box<dyn Osd> {
+                Box::new(FakeOsd {
+                    calls: calls.clone(),
+                    name,
+                    running,
+                })
+            };
+
+            let app = App {
+                config: Arc::new(RwLock::new(config)),
+                ui: FakeUi(calls.clone()),
+                recent_replays: Arc::new(RwLock::new(RecentReplays::default())),
+                tray_handle: FakeTray(calls.clone()),
+                gpu_screen_recorder: FakeRecorder {
+                    calls: calls.clone(),
+                    running: setup.recording,
+                    installed: setup.installed,
+                },
+                window_history: Arc::new(RwLock::new(WindowHistory::default())),
+                feedback: Feedback::with_osds(
+                    FeedbackBackend::Osd,
+                    setup.events,
+                    osd("osd", setup.osd),
+                    osd("notification", setup.notifications),
+                    osd("overlay", true),
+                ),
+                window_source: None,
+                shortcuts_tx,
+                configure_shortcuts_tx,
+                transcode_tx,
+                janitor_tx,
+                remux_locks: RemuxLocks::default(),
+            };
+
+            Self {
+                app,
+                calls,
+                action_rx,
+                configure_shortcuts_rx,
+                transcode_rx,
+                janitor_rx,
+            }
+        }
+
+        fn take_calls(&self) -> Vec<String> {
+            self.calls.take()
+        }
+
+        fn config_saved(&mut self) -> bool {
+            matches!(self.action_rx.try_recv(), Ok(ActionEvent::ConfigSaved))
+        }
+    }
+
+    /// The config, recent replays and retention ledger are written to a temporary directory
+    /// shared by the whole test run (see `utils::config_dir`). Tests using them run one at a
+    /// time and start from the default config.
+    async fn isolated() -> tokio::sync::MutexGuard<'static, ()> {
+        static LOCK: Mutex<()> = Mutex::const_new(());
+
+        let guard = LOCK.lock().await;
+        let _ = std::fs::remove_file(utils::config_dir().join("trayplay.toml"));
+        guard
+    }
+
+    fn replay_path() -> PathBuf {
+        Path::new("/nonexistent/Satisfactory/replay.mkv").to_path_buf()
+    }
+
+    #[tokio::test]
+    async fn save_replay() {
+        let _isolated = isolated().await;
+        let mut harness = Harness::new(Setup::default()).await;
+
+        harness
+            .app
+            .handle_action(ActionEvent::SaveReplay)
+            .await
+            .unwrap();
+        assert_eq!(harness.take_calls(), ["recorder save"]);
+    }
+
+    #[tokio::test]
+    async fn save_replay_without_recorder() {
+        let _isolated = isolated().await;
+        let mut harness = Harness::new(Setup {
+            recording: false,
+            ..Default::default()
+        })
+        .await;
+
+        let err = harness
+            .app
+            .handle_action(ActionEvent::SaveReplay)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Replay recording is either turned off or has crashed."
+        );
+    }
+
+    #[tokio::test]
+    async fn toggle_replay() {
+        let _isolated = isolated().await;
+        let mut harness = Harness::new(Setup {
+            recording: false,
+            ..Default::default()
+        })
+        .await;
+
+        harness
+            .app
+            .handle_action(ActionEvent::ToggleReplay)
+            .await
+            .unwrap();
+        assert_eq!(
+            harness.take_calls(),
+            [
+                "recorder start",
+                "osd Replay recording started",
+                "tray update"
+            ]
+        );
+        assert!(harness.app.config.read().await.recording_enabled);
+        assert!(harness.config_saved());
+
+        harness
+            .app
+            .handle_action(ActionEvent::ToggleReplay)
+            .await
+            .unwrap();
+        assert_eq!(
+            harness.take_calls(),
+            [
+                "recorder stop",
+                "osd Replay recording stopped",
+                "tray update"
+            ]
+        );
+        assert!(!harness.app.config.read().await.recording_enabled);
+        assert!(harness.config_saved());
+    }
+
+    #[tokio::test]
+    async fn toggle_replay_without_gpu_screen_recorder() {
+        let _isolated = isolated().await;
+        let mut harness = Harness::new(Setup {
+            recording: false,
+            installed: false,
+            ..Default::default()
+        })
+        .await;
+        let recording_enabled = harness.app.config.read().await.recording_enabled;
+
+        let err = harness
+            .app
+            .handle_action(ActionEvent::ToggleReplay)
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "gpu-screen-recorder is not installed!");
+        assert_eq!(harness.take_calls(), ["recorder start"]);
+        assert_eq!(
+            harness.app.config.read().await.recording_enabled,
+            recording_enabled
+        );
+        assert!(!harness.config_saved());
+
+        // The failure doesn't get in the way of other actions
+        harness
+            .app
+            .handle_action(ActionEvent::ShowWindow("settings".to_string()))
+            .await
+            .unwrap();
+        assert_eq!(harness.take_calls(), ["window settings"]);
+    }
+
+    #[tokio::test]
+    async fn mark_moment() {
+        let _isolated = isolated().await;
+        let mut harness = Harness::new(Setup::default()).await;
+
+        harness
+            .app
+            .handle_action(ActionEvent::MarkMoment)
+            .await
+            .unwrap();
+        assert_eq!(harness.take_calls(), ["recorder mark", "osd Moment marked"]);
+    }
+
+    #[tokio::test]
+    async fn mark_moment_without_recorder() {
+        let _isolated = isolated().await;
+        let mut harness = Harness::new(Setup {
+            recording: false,
+            ..Default::default()
+        })
+        .await;
+
+        let err = harness
+            .app
+            .handle_action(ActionEvent::MarkMoment)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Cannot mark a moment while replay recording is turned off."
+        );
+        assert_eq!(harness.take_calls(), ["recorder mark"]);
+    }
+
+    #[tokio::test]
+    async fn feedback_falls_back_to_other_services() {
+        let _isolated = isolated().await;
+        let mut harness = Harness::new(Setup {
+            osd: false,
+            ..Default::default()
+        })
+        .await;
+        harness
+            .app
+            .handle_action(ActionEvent::MarkMoment)
+            .await
+            .unwrap();
+        assert_eq!(
+            harness.take_calls(),
+            ["recorder mark", "notification Moment marked"]
+        );
+
+        let mut harness = Harness::new(Setup {
+            osd: false,
+            notifications: false,
+            ..Default::default()
+        })
+        .await;
+        harness
+            .app
+            .handle_action(ActionEvent::MarkMoment)
+            .await
+            .unwrap();
+        assert_eq!(
+            harness.take_calls(),
+            ["recorder mark", "overlay Moment marked"]
+        );
+    }
+
+    #[tokio::test]
+    async fn config_saved_restarts_recorder() {
+        let _isolated = isolated().await;
+        let mut harness = Harness::new(Setup::default()).await;
+
+        harness
+            .app
+            .handle_action(ActionEvent::ConfigSaved)
+            .await
+            .unwrap();
+        assert_eq!(harness.take_calls(), ["recorder restart"]);
+        assert!(harness.janitor_rx.try_recv().is_ok());
+    }
+
+    #[tokio::test]
+    async fn config_saved_leaves_stopped_recorder_alone() {
+        let _isolated = isolated().await;
+        let mut harness = Harness::new(Setup {
+            recording: false,
+            ..Default::default()
+        })
+        .await;
+
+        harness
+            .app
+            .handle_action(ActionEvent::ConfigSaved)
+            .await
+            .unwrap();
+        assert!(harness.take_calls().is_empty());
+    }
+
+    #[tokio::test]
+    async fn replay_saved() {
+        let _isolated = isolated().await;
+        let mut harness = Harness::new(Setup::default()).await;
+        harness
+            .app
+            .config
+            .write()
+            .await
+            .transcode_profiles
+            .push(TranscodeProfile {
+                name: "720p".to_string(),
+                suffix: "720p".to_string(),
+                auto: true,
+                container: Container::MP4,
+                max_height: Some(720),
+                max_size_mb: None,
+            });
+
+        harness
+            .app
+            .handle_action(ActionEvent::ReplaySaved(
+                replay_path(),
+                "Satisfactory".to_string(),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(
+            harness.take_calls(),
+            ["osd Replay from \"Satisfactory\" saved!", "tray update"]
+        );
+        assert_eq!(
+            harness.app.recent_replays.read().await.replays()[0].path,
+            replay_path()
+        );
+        let job = harness.transcode_rx.try_recv().unwrap();
+        assert_eq!(job.input, replay_path());
+        assert_eq!(job.profile.name, "720p");
+        assert!(harness.janitor_rx.try_recv().is_ok());
+    }
+
+    #[tokio::test]
+    async fn toggle_keep_replay() {
+        let _isolated = isolated().await;
+        let mut harness = Harness::new(Setup::default()).await;
+
+        harness
+            .app
+            .handle_action(ActionEvent::ToggleKeepReplay(replay_path()))
+            .await
+            .unwrap();
+        assert!(
+            harness
+                .app
+                .recent_replays
+                .read()
+                .await
+                .is_kept(&replay_path())
+        );
+        assert_eq!(harness.take_calls(), ["tray update"]);
+    }
+
+    #[tokio::test]
+    async fn transcode() {
+        let _isolated = isolated().await;
+        let mut harness = Harness::new(Setup::default()).await;
+
+        let err = harness
+            .app
+            .handle_action(ActionEvent::Transcode(replay_path(), "4K".to_string()))
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Conversion profile 4K doesn't exist");
+
+        // One of the default profiles
+        harness
+            .app
+            .handle_action(ActionEvent::Transcode(replay_path(), "720p".to_string()))
+            .await
+            .unwrap();
+        let job = harness.transcode_rx.try_recv().unwrap();
+        assert_eq!(job.input, replay_path());
+        assert_eq!(job.app_name, "unknown");
+
+        harness
+            .app
+            .handle_action(ActionEvent::TranscodeProgress(Some(
+                "Converting to 720p: 50%".to_string(),
+            )))
+            .await
+            .unwrap();
+        assert_eq!(
+            harness.take_calls(),
+            ["tray status Some(\"Converting to 720p: 50%\")"]
+        );
+
+        let output = Path::new("/nonexistent/Satisfactory/replay_720p.mp4").to_path_buf();
+        harness
+            .app
+            .handle_action(ActionEvent::TranscodeFinished(
+                output.clone(),
+                "Satisfactory".to_string(),
+                "720p".to_string(),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(
+            harness.take_calls(),
+            ["osd Replay converted to 720p", "tray update"]
+        );
+        assert_eq!(
+            harness.app.recent_replays.read().await.replays()[0].path,
+            output
+        );
+    }
+
+    #[tokio::test]
+    async fn transcode_queue_full() {
+        let _isolated = isolated().await;
+        let mut harness = Harness::new(Setup::default()).await;
+
+        for _ in 0..8 {
+            harness
+                .app
+                .handle_action(ActionEvent::Transcode(replay_path(), "720p".to_string()))
+                .await
+                .unwrap();
+        }
+
+        // Fails right away instead of waiting for the worker
+        let err = harness
+            .app
+            .handle_action(ActionEvent::Transcode(replay_path(), "720p".to_string()))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "Too many conversions are queued, {} wasn't converted to 720p",
+                replay_path().display()
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn config_changes_from_tray() {
+        let _isolated = isolated().await;
+        let mut harness = Harness::new(Setup::default()).await;
+
+        for action in [
+            ActionEvent::SetFramerate(144),
+            ActionEvent::SetQuality(Quality::Medium),
+            ActionEvent::SetReplayDuration(120),
+            ActionEvent::SetVideoSource("DP-1".to_string()),
+        ] {
+            harness.app.handle_action(action).await.unwrap();
+            assert!(harness.config_saved());
+            assert_eq!(harness.take_calls(), ["tray update"]);
+        }
+
+        let config = harness.app.config.read().await;
+        assert_eq!(config.framerate, 144);
+        assert_eq!(config.quality, Quality::Medium);
+        assert_eq!(config.replay_duration_secs, 120);
+        assert_eq!(config.screen, "DP-1");
+    }
+
+    #[tokio::test]
+    async fn shortcuts() {
+        let _isolated = isolated().await;
+        let mut harness = Harness::new(Setup::default()).await;
+
+        harness
+            .app
+            .handle_action(ActionEvent::ShortcutsStatusChanged)
+            .await
+            .unwrap();
+        assert_eq!(harness.take_calls(), ["tray update"]);
+
+        harness
+            .app
+            .handle_action(ActionEvent::ConfigureShortcuts)
+            .await
+            .unwrap();
+        assert!(harness.configure_shortcuts_rx.try_recv().is_ok());
+    }
+
+    #[tokio::test]
+    async fn ui_actions() {
+        let _isolated = isolated().await;
+        let mut harness = Harness::new(Setup::default()).await;
+
+        for action in [
+            ActionEvent::ShowWindow("settings".to_string()),
+            ActionEvent::CopyReplayPath(replay_path()),
+            ActionEvent::ShowInfo("TrayPlay".to_string(), "Hello".to_string()),
+            ActionEvent::ShowError("TrayPlay".to_string(), "Oh no".to_string()),
+            ActionEvent::Unknown,
+        ] {
+            harness.app.handle_action(action).await.unwrap();
+        }
+
+        assert_eq!(
+            harness.take_calls(),
+            [
+                "window settings",
+                "clipboard /nonexistent/Satisfactory/replay.mkv",
+                "info TrayPlay: Hello",
+                "error TrayPlay: Oh no"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn errors_shown_as_feedback() {
+        let _isolated = isolated().await;
+        let mut harness = Harness::new(Setup {
+            events: FeedbackEvents {
+                error: Some(FeedbackBackend::Overlay),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .await;
+
+        harness
+            .app
+            .handle_action(ActionEvent::ShowError(
+                "TrayPlay".to_string(),
+                "Oh no".to_string(),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(harness.take_calls(), ["overlay Oh no"]);
+    }
+
+    #[tokio::test]
+    async fn errors_not_hidden() {
+        let _isolated = isolated().await;
+        let mut harness = Harness::new(Setup {
+            events: FeedbackEvents {
+                error: Some(FeedbackBackend::None),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .await;
+
+        harness
+            .app
+            .handle_action(ActionEvent::ShowError(
+                "TrayPlay".to_string(),
+                "Oh no".to_string(),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(harness.take_calls(), ["error TrayPlay: Oh no"]);
+    }
+
+    #[tokio::test]
+    async fn apply_retention() {
+        let _isolated = isolated().await;
+        let mut harness = Harness::new(Setup::default()).await;
+
+        harness
+            .app
+            .handle_action(ActionEvent::ApplyRetention)
+            .await
+            .unwrap();
+        assert!(harness.janitor_rx.try_recv().is_ok());
+    }
+}
@@ -1,9 +1,9 @@
 use int_enum::IntEnum;
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::{collections::BTreeMap, path::PathBuf};
 use tokio::sync::mpsc::Sender;
 
-use crate::ActionEvent;
+use crate::{ActionEvent, utils};
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Config {
@@ -21,6 +21,53 @@ pub struct Config {
     pub replay_duration_secs: i64,
     #[serde(default = "default_file_name_pattern")]
     pub file_name_pattern: String,
+    #[serde(default = "default_bool_true")]
+    pub metadata_sidecar: bool,
+    #[serde(default = "default_bool_true")]
+    pub embed_tags: bool,
+    #[serde(default)]
+    pub post_save_hooks: Vec<String>,
+    #[serde(default = "default_post_save_hook_timeout_secs")]
+    pub post_save_hook_timeout_secs: u64,
+    #[serde(default = "default_transcode_profiles")]
+    pub transcode_profiles: Vec<TranscodeProfile>,
+    #[serde(default)]
+    pub retention: RetentionPolicy,
+    #[serde(default = "default_disk_space_warning_mb")]
+    pub disk_space_warning_mb: u64,
+    #[serde(default = "default_disk_space_minimum_mb")]
+    pub disk_space_minimum_mb: u64,
+    #[serde(default)]
+    pub steam_library_folders: Vec<PathBuf>,
+    #[serde(default)]
+    pub app_alias_rules: Vec<AppAliasRule>,
+    #[serde(default)]
+    pub replay_attribution: ReplayAttribution,
+    #[serde(default)]
+    pub active_window_source: WindowSourceKind,
+    /// Loaded instead of the built-in KWin script, for development
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kwin_script_path: Option<PathBuf>,
+    #[serde(default = "default_recent_replays_limit")]
+    pub recent_replays_limit: usize,
+    #[serde(default)]
+    pub feedback_backend: FeedbackBackend,
+    #[serde(default)]
+    pub feedback_events: FeedbackEvents,
+    #[serde(default = "default_tray_primary_action")]
+    pub tray_primary_action: TrayAction,
+    #[serde(default)]
+    pub tray_middle_click_action: TrayAction,
+    #[serde(default)]
+    pub tray_scroll_action: TrayScrollAction,
+    #[serde(default = "default_tray_scroll_step_secs")]
+    pub tray_scroll_step_secs: i64,
+    #[serde(default = "default_shortcuts")]
+    pub shortcuts: Vec<Shortcut>,
+
+    /// Renames detected app names, e.g. `"UnrealWindow" = "Satisfactory"`
+    #[serde(default)]
+    pub app_aliases: BTreeMap<String, String>,
 
     #[serde(skip, default = "Option::default")]
     action_event_tx: Option<Sender<ActionEvent>>,
@@ -28,7 +75,7 @@ pub struct Config {
 
 impl Config {
     pub async fn load(action_event_tx: Sender<ActionEvent>) -> Self {
-        let mut path = dirs::config_dir().unwrap();
+        let mut path = utils::config_dir();
         path.push("trayplay.toml");
 
         match std::fs::read_to_string(path) {
@@ -40,16 +87,17 @@ impl Config {
             }
             Err(err) => {
                 println!("{}", err);
-                Config::default()
+                Self {
+                    action_event_tx: Some(action_event_tx),
+                    ..Self::default()
+                }
             }
         }
     }
 
+    /// Writes the config and restarts the recorder through [`ActionEvent::ConfigSaved`].
     pub async fn save(&self) {
-        let mut path = dirs::config_dir().unwrap();
-        path.push("trayplay.toml");
-
-        std::fs::write(path, toml::to_string(&self).unwrap()).expect("Failed to write config file");
+        self.write();
 
         self.action_event_tx
             .as_ref()
@@ -58,13 +106,21 @@ impl Config {
             .await
             .unwrap();
     }
+
+    /// Writes the config without letting anyone know, for settings the recorder doesn't use.
+    pub fn write(&self) {
+        let mut path = utils::config_dir();
+        path.push("trayplay.toml");
+
+        std::fs::write(path, toml::to_string(&self).unwrap()).expect("Failed to write config file");
+    }
 }
 
 impl Default for Config {
     fn default() -> Self {
         println!("Config missing or broken. Replacing with defaults");
 
-        let mut path = dirs::config_dir().unwrap();
+        let mut path = utils::config_dir();
         path.push("trayplay.toml");
 
         let instance = Self {
@@ -74,11 +130,33 @@ impl Default for Config {
             framerate: 60,
             clear_buffer_on_save: true,
             quality: Quality::Ultra,
-            replay_directory: dirs::video_dir().unwrap(),
+            replay_directory: utils::video_dir(),
             container: Container::MKV,
             codec: Codec::H264,
             replay_duration_secs: 180,
             file_name_pattern: default_file_name_pattern(),
+            metadata_sidecar: true,
+            embed_tags: true,
+            post_save_hooks: vec![],
+            post_save_hook_timeout_secs: default_post_save_hook_timeout_secs(),
+            transcode_profiles: default_transcode_profiles(),
+            retention: RetentionPolicy::default(),
+            disk_space_warning_mb: default_disk_space_warning_mb(),
+            disk_space_minimum_mb: default_disk_space_minimum_mb(),
+            steam_library_folders: vec![],
+            app_alias_rules: vec![],
+            replay_attribution: ReplayAttribution::default(),
+            active_window_source: WindowSourceKind::default(),
+            kwin_script_path: None,
+            app_aliases: BTreeMap::new(),
+            recent_replays_limit: default_recent_replays_limit(),
+            feedback_backend: FeedbackBackend::default(),
+            feedback_events: FeedbackEvents::default(),
+            tray_primary_action: default_tray_primary_action(),
+            tray_middle_click_action: TrayAction::default(),
+            tray_scroll_action: TrayScrollAction::default(),
+            tray_scroll_step_secs: default_tray_scroll_step_secs(),
+            shortcuts: default_shortcuts(),
             action_event_tx: None,
         };
 
@@ -172,6 +250,179 @@ impl Default for Codec {
     }
 }
 
+/// Where to learn which window is active from.
+#[derive(Serialize, Deserialize, PartialEq, Clone, Copy, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum WindowSourceKind {
+    /// First one that works in the current session
+    #[default]
+    Auto,
+    Kwin,
+    GnomeShell,
+    Hyprland,
+    Wlroots,
+    X11,
+    None,
+}
+
+/// Which app a saved replay is named after.
+#[derive(Serialize, Deserialize, PartialEq, Clone, Copy, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ReplayAttribution {
+    /// Fullscreen app active when the replay is saved
+    Current,
+    /// Last app that was fullscreen during the replay
+    LastFullscreen,
+    /// App that was fullscreen for most of the replay, the current one if there is none
+    #[default]
+    LongestFullscreen,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Copy, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum FeedbackBackend {
+    #[default]
+    Osd,
+    Notifications,
+    /// Small window shown by TrayPlay itself
+    Overlay,
+    None,
+}
+
+/// Feedback backend for each kind of event, `feedback_backend` is used for those left out.
+#[derive(Serialize, Deserialize, PartialEq, Clone, Copy, Debug, Default)]
+pub struct FeedbackEvents {
+    /// Replay saved or converted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub saved: Option<FeedbackBackend>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub marked: Option<FeedbackBackend>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub started: Option<FeedbackBackend>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stopped: Option<FeedbackBackend>,
+    /// Errors are shown in a dialog unless set to a backend other than `none`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<FeedbackBackend>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Copy, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum TrayAction {
+    #[default]
+    None,
+    /// Only works as the primary action, the menu can't be opened programmatically
+    Menu,
+    SaveReplay,
+    ToggleReplay,
+    OpenSettings,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Copy, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum TrayScrollAction {
+    #[default]
+    None,
+    ReplayDuration,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+pub struct Shortcut {
+    pub id: String,
+    pub description: String,
+    /// Only a hint for the portal, the user has the final say over the trigger
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trigger: Option<String>,
+    pub action: ShortcutAction,
+}
+
+impl Shortcut {
+    fn new(id: &str, description: &str, trigger: &str, action: ShortcutAction) -> Self {
+        Self {
+            id: id.to_string(),
+            description: description.to_string(),
+            trigger: Some(trigger.to_string()),
+            action,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum ShortcutAction {
+    SaveReplay,
+    ToggleReplay,
+    OpenSettings,
+    Quit,
+    MarkMoment,
+    SetFramerate(u32),
+    SetQuality(Quality),
+    SetReplayDuration(i64),
+    SetVideoSource(String),
+}
+
+/// Post-processing of saved replays. Without `max_height` and `max_size_mb` the replay is
+/// only remuxed into `container`, otherwise it's re-encoded with x264.
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+pub struct TranscodeProfile {
+    pub name: String,
+    /// Appended to the replay file name
+    pub suffix: String,
+    /// Run after every saved replay instead of only from the tray
+    #[serde(default)]
+    pub auto: bool,
+    pub container: Container,
+    #[serde(default)]
+    pub max_height: Option<u32>,
+    #[serde(default)]
+    pub max_size_mb: Option<u32>,
+}
+
+/// Limits on how many replays are kept in `replay_directory`. Everything is unlimited by default.
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Default)]
+pub struct RetentionPolicy {
+    #[serde(default)]
+    pub max_total_size_gb: Option<u64>,
+    #[serde(default)]
+    pub max_age_days: Option<u32>,
+    #[serde(default)]
+    pub max_count_per_app: Option<usize>,
+    #[serde(default)]
+    pub action: RetentionAction,
+}
+
+impl RetentionPolicy {
+    pub fn is_enabled(&self) -> bool {
+        self.max_total_size_gb.is_some()
+            || self.max_age_days.is_some()
+            || self.max_count_per_app.is_some()
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum RetentionAction {
+    #[default]
+    Trash,
+    Delete,
+    MoveTo(PathBuf),
+}
+
+/// Gives windows matching all of the set conditions the app name `name`.
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+pub struct AppAliasRule {
+    pub name: String,
+    #[serde(default)]
+    pub desktop_file: Option<String>,
+    #[serde(default)]
+    pub exe: Option<PathBuf>,
+    /// Regular expression matched against the window title
+    #[serde(default)]
+    pub title: Option<String>,
+    #[serde(default)]
+    pub steam_app_id: Option<u32>,
+}
+
 fn default_bool_true() -> bool {
     true
 }
@@ -179,3 +430,85 @@ fn default_bool_true() -> bool {
 fn default_file_name_pattern() -> String {
     "%app%/%app%_replay_%year%-%month%-%day%_%hour%-%minute%-%second%".to_string()
 }
+
+fn default_post_save_hook_timeout_secs() -> u64 {
+    300
+}
+
+fn default_disk_space_warning_mb() -> u64 {
+    5120
+}
+
+fn default_disk_space_minimum_mb() -> u64 {
+    512
+}
+
+fn default_recent_replays_limit() -> usize {
+    10
+}
+
+fn default_tray_primary_action() -> TrayAction {
+    TrayAction::Menu
+}
+
+fn default_tray_scroll_step_secs() -> i64 {
+    30
+}
+
+fn default_transcode_profiles() -> Vec<TranscodeProfile> {
+    vec![
+        TranscodeProfile {
+            name: "MP4".to_string(),
+            suffix: "remux".to_string(),
+            auto: false,
+            container: Container::MP4,
+            max_height: None,
+            max_size_mb: None,
+        },
+        TranscodeProfile {
+            name: "720p".to_string(),
+            suffix: "720p".to_string(),
+            auto: false,
+            container: Container::MP4,
+            max_height: Some(720),
+            max_size_mb: None,
+        },
+        TranscodeProfile {
+            name: "25 MB".to_string(),
+            suffix: "25mb".to_string(),
+            auto: false,
+            container: Container::MP4,
+            max_height: None,
+            max_size_mb: Some(25),
+        },
+    ]
+}
+
+fn default_shortcuts() -> Vec<Shortcut> {
+    vec![
+        Shortcut::new(
+            "save-replay",
+            "Save replay",
+            "ALT+F10",
+            ShortcutAction::SaveReplay,
+        ),
+        Shortcut::new(
+            "toggle-replay",
+            "Toggle replay",
+            "ALT+SHIFT+F10",
+            ShortcutAction::ToggleReplay,
+        ),
+        Shortcut::new(
+            "mark-moment",
+            "Mark moment",
+            "ALT+F9",
+            ShortcutAction::MarkMoment,
+        ),
+        Shortcut::new(
+            "quit",
+            "Quit program",
+            "ALT+SHIFT+F11",
+            ShortcutAction::Quit,
+        ),
+    ]
+}
@@ -0,0 +1,259 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt::Write,
+    path::{Path, PathBuf},
+    process::Stdio,
+    sync::{Arc, Mutex},
+};
+
+use log::{debug, info};
+use time::OffsetDateTime;
+use tokio::{
+    fs,
+    process::Command,
+    sync::{OwnedRwLockWriteGuard, RwLock},
+};
+
+use crate::{active_window::ActiveWindow, markers::Chapters, utils::check_free_space};
+
+/// Metadata written into the replay container itself, so it survives renames and uploads.
+#[derive(Debug, Default)]
+pub struct ContainerMetadata {
+    pub tags: Vec<(&'static str, String)>,
+    pub chapters: Chapters,
+}
+
+impl ContainerMetadata {
+    pub fn is_empty(&self) -> bool {
+        self.tags.is_empty() && self.chapters.starts.is_empty()
+    }
+
+    /// Only uses tags that both MKV and MP4 understand.
+    pub fn tag_replay(&mut self, active_window: &ActiveWindow) {
+        let now = OffsetDateTime::now_local().unwrap_or_else(|_| OffsetDateTime::now_utc());
+
+        self.tags = vec![
+            ("title", format!("{} replay", active_window.app_name)),
+            ("artist", active_window.app_name.clone()),
+            (
+                "date",
+                format!("{}-{:0>2}-{:0>2}", now.year(), now.month() as u8, now.day()),
+            ),
+        ];
+
+        if !active_window.title.is_empty() {
+            self.tags.push(("comment", active_window.title.clone()));
+        }
+    }
+
+    fn to_ffmetadata(&self) -> String {
+        let mut metadata = ";FFMETADATA1\n".to_string();
+
+        for (key, value) in &self.tags {
+            let _ = writeln!(metadata, "{}={}", key, escape(value));
+        }
+
+        let chapters = &self.chapters;
+        for (i, start) in chapters.starts.iter().enumerate() {
+            // A chapter lasts until the next one, the last one until the end of the replay
+            let end = chapters
+                .starts
+                .get(i + 1)
+                .unwrap_or(&chapters.replay_length);
+            let _ = write!(
+                metadata,
+                "\n[CHAPTER]\nTIMEBASE=1/1000\nSTART={}\nEND={}\ntitle=Mark {}\n",
+                start.as_millis(),
+                end.as_millis(),
+                i + 1
+            );
+        }
+
+        metadata
+    }
+}
+
+/// Replays whose metadata is still being embedded. The remux replaces the file when it's
+/// done, so anything trashing, moving or converting a replay waits for it first.
+#[derive(Debug, Clone, Default)]
+pub struct RemuxLocks {
+    locks: Arc<Mutex<HashMap<PathBuf, Arc<RwLock<()>>>>>,
+}
+
+/// Held while a replay is being remuxed, see [`RemuxLocks`].
+pub struct RemuxGuard {
+    locks: RemuxLocks,
+    path: PathBuf,
+    lock: Arc<RwLock<()>>,
+    _guard: OwnedRwLockWriteGuard<()>,
+}
+
+impl RemuxLocks {
+    pub fn lock(&self, path: &Path) -> RemuxGuard {
+        let lock = Arc::new(RwLock::new(()));
+        let guard = lock
+            .clone()
+            .try_write_owned()
+            .expect("nobody else has this lock yet");
+        self.locks
+            .lock()
+            .unwrap()
+            .insert(path.to_path_buf(), lock.clone());
+
+        RemuxGuard {
+            locks: self.clone(),
+            path: path.to_path_buf(),
+            lock,
+            _guard: guard,
+        }
+    }
+
+    /// Returns once the replay at `path` isn't being remuxed anymore.
+    pub async fn wait(&self, path: &Path) {
+        let lock = self.locks.lock().unwrap().get(path).cloned();
+        if let Some(lock) = lock {
+            let _ = lock.read().await;
+        }
+    }
+}
+
+impl Drop for RemuxGuard {
+    fn drop(&mut self) {
+        let mut locks = self.locks.locks.lock().unwrap();
+        // The same path may have been locked again in the meantime
+        if locks
+            .get(&self.path)
+            .is_some_and(|lock| Arc::ptr_eq(lock, &self.lock))
+        {
+            locks.remove(&self.path);
+        }
+    }
+}
+
+/// Writes tags and chapters into the replay using ffmpeg. When that isn't possible, chapters
+/// are saved next to it in the ffmetadata format, which players like mpv can load with
+/// `--chapters-file`.
+pub async fn write_metadata(
+    path: &Path,
+    metadata: &ContainerMetadata,
+) -> Result<(), Box<dyn Error>> {
+    if metadata.is_empty() {
+        return Ok(());
+    }
+
+    let metadata_path = with_suffix(path, ".chapters.txt");
+    fs::write(&metadata_path, metadata.to_ffmetadata()).await?;
+
+    // Errors aren't Send, so keep only the message while awaiting
+    let result = remux_with_metadata(path, &metadata_path)
+        .await
+        .map_err(|err| err.to_string());
+
+    match result {
+        Ok(()) => fs::remove_file(&metadata_path).await?,
+        Err(err) if !metadata.chapters.starts.is_empty() => info!(
+            "Cannot embed metadata, keeping chapters in {}: {}",
+            metadata_path.display(),
+            err
+        ),
+        Err(err) => {
+            // Tags are optional, don't bother the user on every save when ffmpeg is missing
+            info!("Cannot embed metadata into {}: {}", path.display(), err);
+            fs::remove_file(&metadata_path).await?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn remux_with_metadata(path: &Path, metadata_path: &Path) -> Result<(), Box<dyn Error>> {
+    let temp_path = path.with_extension(format!(
+        "metadata.{}",
+        path.extension().unwrap_or_default().display()
+    ));
+    // The copy is as big as the replay itself until it replaces it
+    check_free_space(
+        path.parent().unwrap_or(Path::new("/")),
+        fs::metadata(path).await?.len(),
+    )?;
+
+    let output = Command::new("ffmpeg")
+        .args(["-y", "-loglevel", "error", "-i"])
+        .arg(path)
+        .arg("-i")
+        .arg(metadata_path)
+        .args([
+            "-map",
+            "0",
+            "-map_metadata",
+            "1",
+            "-map_chapters",
+            "1",
+            "-codec",
+            "copy",
+        ])
+        .arg(&temp_path)
+        .stdin(Stdio::null())
+        .output()
+        .await?;
+
+    if !output.status.success() {
+        let _ = fs::remove_file(&temp_path).await;
+        return Err(String::from_utf8_lossy(&output.stderr).trim().into());
+    }
+
+    fs::rename(&temp_path, path).await?;
+    debug!("Embedded metadata into {}", path.display());
+
+    Ok(())
+}
+
+/// Special characters of the ffmetadata format have to be escaped with a backslash.
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for char in value.chars() {
+        if matches!(char, '=' | ';' | '#' | '\\' | '\n') {
+            escaped.push('\\');
+        }
+        escaped.push(char);
+    }
+    escaped
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_os_string();
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn wait_blocks_until_remux_is_done() {
+        let locks = RemuxLocks::default();
+        let path = Path::new("/nonexistent/replay.mkv");
+
+        // Nothing to wait for
+        locks.wait(path).await;
+
+        let guard = locks.lock(path);
+        let waiting = tokio::spawn({
+            let locks = locks.clone();
+            async move { locks.wait(Path::new("/nonexistent/replay.mkv")).await }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!waiting.is_finished());
+
+        drop(guard);
+        tokio::time::timeout(Duration::from_secs(5), waiting)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(locks.locks.lock().unwrap().is_empty());
+    }
+}
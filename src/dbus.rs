@@ -0,0 +1,225 @@
+//! Names TrayPlay uses on the session bus. The KWin script gets them filled in when it's
+//! loaded, so they only have to be changed here.
+
+/// Well-known bus name, also used as app ID and desktop file name
+pub const SERVICE_NAME: &str = "ovh.kabus.TrayPlay";
+
+/// Object path of the active window manager and marker service
+pub const OBJECT_PATH: &str = "/ovh/kabus/TrayPlay";
+
+/// Method the KWin script calls on every window change
+pub const SET_ACTIVE_WINDOW_METHOD: &str = "SetActiveWindow";
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader},
+        process::{Child, Command, Stdio},
+        time::Duration,
+    };
+
+    use tokio::sync::mpsc;
+    use zbus::{connection::Builder, object_server::Interface, zvariant::Type};
+
+    use super::*;
+    use crate::active_window::ActiveWindowManager;
+
+    const KWIN_SCRIPT: &str = include_str!("../dist/kwin_script.js");
+    const GNOME_EXTENSION: &str =
+        include_str!("../dist/gnome-extension/trayplay@kabus.ovh/extension.js");
+
+    /// Arguments in the order and with the types the KWin script and GNOME extension send,
+    /// checked against both below
+    type SetActiveWindowArgs<'a> = (
+        &'a str,
+        &'a str,
+        bool,
+        i32,
+        &'a str,
+        i32,
+        i32,
+        i32,
+        i32,
+        &'a str,
+    );
+
+    /// Private session bus, stopped when dropped.
+    struct TestBus {
+        daemon: Child,
+        address: String,
+    }
+
+    impl TestBus {
+        fn start() -> Self {
+            let mut daemon = Command::new("dbus-daemon")
+                .args(["--session", "--print-address", "--nofork"])
+                .stdout(Stdio::piped())
+                .stderr(Stdio::null())
+                .spawn()
+                .expect("dbus-daemon is needed to run this test");
+
+            let mut address = String::new();
+            BufReader::new(daemon.stdout.take().unwrap())
+                .read_line(&mut address)
+                .unwrap();
+
+            Self {
+                daemon,
+                address: address.trim().to_string(),
+            }
+        }
+    }
+
+    impl Drop for TestBus {
+        fn drop(&mut self) {
+            let _ = self.daemon.kill();
+            let _ = self.daemon.wait();
+        }
+    }
+
+    #[tokio::test]
+    async fn set_active_window_call_reaches_the_channel() {
+        let bus = TestBus::start();
+        let (window_tx, mut window_rx) = mpsc::channel(1);
+
+        let _service = Builder::address(bus.address.as_str())
+            .unwrap()
+            .name(SERVICE_NAME)
+            .unwrap()
+            .serve_at(OBJECT_PATH, ActiveWindowManager::new(window_tx))
+            .unwrap()
+            .build()
+            .await
+            .unwrap();
+        let client = Builder::address(bus.address.as_str())
+            .unwrap()
+            .build()
+            .await
+            .unwrap();
+
+        let args: SetActiveWindowArgs = (
+            "steam_app_220",
+            "Half-Life 2",
+            true,
+            1234,
+            "steam_app_220",
+            0,
+            0,
+            2560,
+            1440,
+            "DP-1",
+        );
+        client
+            .call_method(
+                Some(SERVICE_NAME),
+                OBJECT_PATH,
+                Some(ActiveWindowManager::name()),
+                SET_ACTIVE_WINDOW_METHOD,
+                &args,
+            )
+            .await
+            .unwrap();
+
+        let window = tokio::time::timeout(Duration::from_secs(5), window_rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(window.desktop_file, "steam_app_220");
+        assert_eq!(window.title, "Half-Life 2");
+        assert!(window.fullscreen);
+        assert_eq!(window.pid, 1234);
+        assert_eq!(window.resource_class, "steam_app_220");
+        assert_eq!(window.geometry.width, 2560.0);
+        assert_eq!(window.geometry.height, 1440.0);
+        assert_eq!(window.output, "DP-1");
+    }
+
+    /// Arguments the KWin script passes to `callDBus`, without the destination.
+    fn kwin_script_arguments() -> Vec<&'static str> {
+        let call = KWIN_SCRIPT
+            .split_once("callDBus(")
+            .and_then(|(_, call)| call.split_once(");"))
+            .map(|(call, _)| call)
+            .expect("kwin_script.js should call callDBus");
+
+        let arguments = call
+            .lines()
+            .map(|line| line.trim().trim_end_matches(','))
+            .filter(|line| !line.is_empty() && !line.starts_with("//"))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            arguments[..4],
+            ["SERVICE", "OBJECT_PATH", "INTERFACE", "METHOD"]
+        );
+
+        arguments[4..].to_vec()
+    }
+
+    /// Type `callDBus` sends a script argument as: KWin turns JS numbers into int32 when
+    /// they are whole and into double otherwise.
+    fn kwin_argument_type(argument: &str) -> char {
+        match argument {
+            "window.desktopFileName"
+            | "window.caption"
+            | "window.resourceClass"
+            | "window.output ? window.output.name : \"\"" => 's',
+            "window.fullScreen" => 'b',
+            // Process IDs are always whole
+            "window.pid" => 'i',
+            argument if argument.starts_with("Math.round(") => 'i',
+            // Geometry is fractional with display scaling
+            argument if argument.starts_with("window.frameGeometry.") => 'd',
+            argument => panic!("type of `{}` in kwin_script.js is unknown", argument),
+        }
+    }
+
+    #[test]
+    fn kwin_script_sends_the_expected_call() {
+        for (constant, placeholder) in [
+            ("SERVICE", "%SERVICE%"),
+            ("OBJECT_PATH", "%OBJECT_PATH%"),
+            ("INTERFACE", "%INTERFACE%"),
+            ("METHOD", "%METHOD%"),
+        ] {
+            let line = format!("const {} = \"{}\";", constant, placeholder);
+            assert!(
+                KWIN_SCRIPT.contains(&line),
+                "kwin_script.js should contain `{}`",
+                line
+            );
+        }
+
+        let signature = format!(
+            "({})",
+            kwin_script_arguments()
+                .into_iter()
+                .map(kwin_argument_type)
+                .collect::<String>()
+        );
+        assert_eq!(signature, SetActiveWindowArgs::SIGNATURE.to_string());
+    }
+
+    #[test]
+    fn gnome_extension_uses_the_same_names() {
+        for (constant, value) in [
+            ("SERVICE", SERVICE_NAME.to_string()),
+            ("OBJECT_PATH", OBJECT_PATH.to_string()),
+            ("INTERFACE", ActiveWindowManager::name().to_string()),
+            ("METHOD", SET_ACTIVE_WINDOW_METHOD.to_string()),
+        ] {
+            let line = format!("const {} = '{}';", constant, value);
+            assert!(
+                GNOME_EXTENSION.contains(&line),
+                "extension.js should contain `{}`",
+                line
+            );
+        }
+
+        let signature = format!("new GLib.Variant('{}'", SetActiveWindowArgs::SIGNATURE);
+        assert!(
+            GNOME_EXTENSION.contains(&signature),
+            "extension.js should send `{}`",
+            signature
+        );
+    }
+}
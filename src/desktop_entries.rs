@@ -0,0 +1,334 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use freedesktop_entry_parser::parse_entry;
+use log::debug;
+
+/// Looks up app names in desktop entries following the XDG desktop entry spec.
+#[derive(Debug)]
+pub struct DesktopEntries {
+    /// Directories containing `applications`, most important first
+    data_dirs: Vec<PathBuf>,
+    /// Locale keys tried for `Name[...]`, most specific first
+    locale_keys: Vec<String>,
+    /// Names of desktop entries found so far, by desktop file ID
+    names: HashMap<String, String>,
+}
+
+impl DesktopEntries {
+    pub fn new(data_dirs: Vec<PathBuf>, locale: Option<&str>) -> Self {
+        Self {
+            data_dirs,
+            locale_keys: locale.map(locale_keys).unwrap_or_default(),
+            names: HashMap::new(),
+        }
+    }
+
+    /// Uses `$XDG_DATA_HOME`, `$XDG_DATA_DIRS`, Flatpak and Snap exports and the
+    /// locale set for messages.
+    pub fn from_env() -> Self {
+        let home = dirs::home_dir().unwrap_or_default();
+        let data_home = std::env::var_os("XDG_DATA_HOME")
+            .filter(|data_home| !data_home.is_empty())
+            .map_or_else(|| home.join(".local/share"), PathBuf::from);
+        let system_dirs = std::env::var("XDG_DATA_DIRS")
+            .ok()
+            .filter(|data_dirs| !data_dirs.is_empty())
+            .unwrap_or("/usr/local/share:/usr/share".to_string());
+
+        let mut data_dirs = vec![data_home.clone()];
+        data_dirs.extend(
+            system_dirs
+                .split(':')
+                .filter(|dir| !dir.is_empty())
+                .map(PathBuf::from),
+        );
+        // Exports are missing from the environment when not started from a desktop session
+        for exports in [
+            data_home.join("flatpak/exports/share"),
+            PathBuf::from("/var/lib/flatpak/exports/share"),
+            PathBuf::from("/var/lib/snapd/desktop"),
+        ] {
+            if !data_dirs.contains(&exports) {
+                data_dirs.push(exports);
+            }
+        }
+
+        let locale = ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .into_iter()
+            .filter_map(|variable| std::env::var(variable).ok())
+            .find(|locale| !locale.is_empty());
+
+        Self::new(data_dirs, locale.as_deref())
+    }
+
+    /// Returns the localized `Name` of the desktop entry with the given ID (e.g.
+    /// `org.kde.dolphin`), or `None` when there is no such entry.
+    pub fn app_name(&mut self, desktop_file_id: &str) -> Option<String> {
+        let desktop_file_id = desktop_file_id.trim_end_matches(".desktop");
+        if desktop_file_id.is_empty() {
+            return None;
+        }
+
+        // Only found names are cached, so apps installed later are still picked up
+        if let Some(name) = self.names.get(desktop_file_id) {
+            return Some(name.clone());
+        }
+
+        let path = self.find(desktop_file_id)?;
+        let name = self.read_name(&path)?;
+        self.names.insert(desktop_file_id.to_string(), name.clone());
+
+        Some(name)
+    }
+
+    fn find(&self, desktop_file_id: &str) -> Option<PathBuf> {
+        // Some windows report the path of their desktop file instead of its ID
+        if desktop_file_id.starts_with('/') {
+            let path = PathBuf::from(format!("{}.desktop", desktop_file_id));
+            return path.is_file().then_some(path);
+        }
+
+        self.data_dirs
+            .iter()
+            .find_map(|data_dir| find_in(&data_dir.join("applications"), desktop_file_id))
+    }
+
+    fn read_name(&self, path: &Path) -> Option<String> {
+        let entry = parse_entry(path)
+            .inspect_err(|err| debug!("Cannot parse {}: {}", path.display(), err))
+            .ok()?;
+        let section = entry.section("Desktop Entry")?;
+
+        self.locale_keys
+            .iter()
+            .find_map(|key| section.attr_with_param("Name", key).first())
+            .or(section.attr("Name").first())
+            .cloned()
+    }
+}
+
+/// Finds the desktop file with the given ID in `directory`. Entries in subdirectories get
+/// IDs with `/` replaced by `-`, e.g. `kde4/dolphin.desktop` is `kde4-dolphin`, so any dash
+/// may stand for a directory. Only directories that exist are looked into, which keeps IDs
+/// with many dashes cheap.
+fn find_in(directory: &Path, desktop_file_id: &str) -> Option<PathBuf> {
+    let path = directory.join(format!("{}.desktop", desktop_file_id));
+    if path.is_file() {
+        return Some(path);
+    }
+
+    desktop_file_id
+        .match_indices('-')
+        .filter(|(dash, _)| *dash > 0)
+        .find_map(|(dash, _)| {
+            let subdirectory = directory.join(&desktop_file_id[..dash]);
+            if subdirectory.is_dir() {
+                find_in(&subdirectory, &desktop_file_id[dash + 1..])
+            } else {
+                None
+            }
+        })
+}
+
+/// Keys of localized values matching `locale` (`lang_COUNTRY.ENCODING@MODIFIER`) in the
+/// order the spec tries them.
+fn locale_keys(locale: &str) -> Vec<String> {
+    let (locale, modifier) = match locale.split_once('@') {
+        Some((locale, modifier)) => (locale, Some(modifier)),
+        None => (locale, None),
+    };
+    let locale = locale.split('.').next().unwrap_or_default();
+    let (lang, country) = match locale.split_once('_') {
+        Some((lang, country)) => (lang, Some(country)),
+        None => (locale, None),
+    };
+
+    if lang.is_empty() || lang == "C" || lang == "POSIX" {
+        return vec![];
+    }
+
+    let mut keys = vec![];
+    if let (Some(country), Some(modifier)) = (country, modifier) {
+        keys.push(format!("{}_{}@{}", lang, country, modifier));
+    }
+    if let Some(country) = country {
+        keys.push(format!("{}_{}", lang, country));
+    }
+    if let Some(modifier) = modifier {
+        keys.push(format!("{}@{}", lang, modifier));
+    }
+    keys.push(lang.to_string());
+
+    keys
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use tempfile::TempDir;
+
+    use super::*;
+
+    /// Data directory with desktop entries at the given paths relative to `applications`.
+    fn data_dir(entries: &[(&str, &str)]) -> TempDir {
+        let data_dir = tempfile::tempdir().unwrap();
+        for (path, contents) in entries {
+            let path = data_dir.path().join("applications").join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+
+        data_dir
+    }
+
+    fn entry(name: &str) -> String {
+        format!("[Desktop Entry]\nType=Application\nName={}\n", name)
+    }
+
+    fn desktop_entries(data_dirs: &[&TempDir], locale: Option<&str>) -> DesktopEntries {
+        DesktopEntries::new(
+            data_dirs
+                .iter()
+                .map(|data_dir| data_dir.path().to_path_buf())
+                .collect(),
+            locale,
+        )
+    }
+
+    #[test]
+    fn finds_entry() {
+        let data_dir = data_dir(&[("org.kde.dolphin.desktop", &entry("Dolphin"))]);
+        let mut entries = desktop_entries(&[&data_dir], None);
+
+        assert_eq!(
+            entries.app_name("org.kde.dolphin").as_deref(),
+            Some("Dolphin")
+        );
+        assert_eq!(
+            entries.app_name("org.kde.dolphin.desktop").as_deref(),
+            Some("Dolphin")
+        );
+        assert_eq!(entries.app_name("org.kde.konsole"), None);
+        assert_eq!(entries.app_name(""), None);
+    }
+
+    #[test]
+    fn earlier_data_dirs_win() {
+        let data_home = data_dir(&[("steam.desktop", &entry("Steam (local)"))]);
+        let system = data_dir(&[
+            ("steam.desktop", &entry("Steam")),
+            ("lutris.desktop", &entry("Lutris")),
+        ]);
+        let mut entries = desktop_entries(&[&data_home, &system], None);
+
+        assert_eq!(entries.app_name("steam").as_deref(), Some("Steam (local)"));
+        assert_eq!(entries.app_name("lutris").as_deref(), Some("Lutris"));
+    }
+
+    #[test]
+    fn finds_entries_in_subdirectories() {
+        let data_dir = data_dir(&[
+            ("kde4/dolphin.desktop", &entry("Dolphin")),
+            ("a-b/c.desktop", &entry("A-B C")),
+            ("x/y-z.desktop", &entry("X Y-Z")),
+            ("p/q/r.desktop", &entry("P Q R")),
+        ]);
+        let mut entries = desktop_entries(&[&data_dir], None);
+
+        assert_eq!(entries.app_name("kde4-dolphin").as_deref(), Some("Dolphin"));
+        assert_eq!(entries.app_name("a-b-c").as_deref(), Some("A-B C"));
+        assert_eq!(entries.app_name("x-y-z").as_deref(), Some("X Y-Z"));
+        assert_eq!(entries.app_name("p-q-r").as_deref(), Some("P Q R"));
+        assert_eq!(entries.app_name("kde4-konsole"), None);
+    }
+
+    #[test]
+    fn finds_entry_by_path() {
+        let data_dir = data_dir(&[("firefox.desktop", &entry("Firefox"))]);
+        let path = data_dir.path().join("applications/firefox");
+        let mut entries = desktop_entries(&[], None);
+
+        assert_eq!(
+            entries.app_name(path.to_str().unwrap()).as_deref(),
+            Some("Firefox")
+        );
+    }
+
+    #[test]
+    fn localized_names() {
+        let data_dir = data_dir(&[
+            (
+                "org.kde.dolphin.desktop",
+                "[Desktop Entry]\nName=Dolphin\nName[de]=Dolphin (de)\nName[pt_BR]=Dolphin (pt_BR)\n",
+            ),
+            (
+                "org.kde.konsole.desktop",
+                "[Desktop Entry]\nName=Konsole\nName[sr@latin]=Konsola\n",
+            ),
+        ]);
+
+        let name = |locale, id| desktop_entries(&[&data_dir], locale).app_name(id);
+        assert_eq!(
+            name(Some("de_DE.UTF-8"), "org.kde.dolphin").as_deref(),
+            Some("Dolphin (de)")
+        );
+        assert_eq!(
+            name(Some("pt_BR.UTF-8"), "org.kde.dolphin").as_deref(),
+            Some("Dolphin (pt_BR)")
+        );
+        assert_eq!(
+            name(Some("fr_FR.UTF-8"), "org.kde.dolphin").as_deref(),
+            Some("Dolphin")
+        );
+        assert_eq!(
+            name(Some("sr_RS@latin"), "org.kde.konsole").as_deref(),
+            Some("Konsola")
+        );
+        assert_eq!(
+            name(Some("C"), "org.kde.dolphin").as_deref(),
+            Some("Dolphin")
+        );
+    }
+
+    #[test]
+    fn entry_without_name() {
+        let data_dir = data_dir(&[("broken.desktop", "[Desktop Entry]\nType=Application\n")]);
+
+        assert_eq!(desktop_entries(&[&data_dir], None).app_name("broken"), None);
+    }
+
+    #[test]
+    fn caches_found_names_only() {
+        let data_dir = data_dir(&[("steam.desktop", &entry("Steam"))]);
+        let mut entries = desktop_entries(&[&data_dir], None);
+
+        assert_eq!(entries.app_name("steam").as_deref(), Some("Steam"));
+        assert_eq!(entries.app_name("lutris"), None);
+
+        fs::remove_file(data_dir.path().join("applications/steam.desktop")).unwrap();
+        fs::write(
+            data_dir.path().join("applications/lutris.desktop"),
+            entry("Lutris"),
+        )
+        .unwrap();
+
+        assert_eq!(entries.app_name("steam").as_deref(), Some("Steam"));
+        assert_eq!(entries.app_name("lutris").as_deref(), Some("Lutris"));
+    }
+
+    #[test]
+    fn locale_key_order() {
+        assert_eq!(
+            locale_keys("sr_RS.UTF-8@latin"),
+            ["sr_RS@latin", "sr_RS", "sr@latin", "sr"]
+        );
+        assert_eq!(locale_keys("de_DE.UTF-8"), ["de_DE", "de"]);
+        assert_eq!(locale_keys("de"), ["de"]);
+        assert!(locale_keys("C.UTF-8").is_empty());
+        assert!(locale_keys("POSIX").is_empty());
+    }
+}
@@ -0,0 +1,344 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use futures::future::LocalBoxFuture;
+use futures_util::StreamExt;
+use log::debug;
+use tokio::sync::{Mutex, mpsc::Sender};
+use zbus::{Connection, proxy, zvariant::Value};
+
+use crate::{
+    ActionEvent,
+    config::{FeedbackBackend, FeedbackEvents},
+    dbus,
+    ui::Toast,
+};
+
+#[proxy(
+    interface = "org.kde.osdService",
+    default_service = "org.kde.plasmashell",
+    default_path = "/org/kde/osdService"
+)]
+trait OsdService {
+    #[zbus(name = "showText")]
+    fn show_text(&self, icon: &str, text: &str) -> zbus::Result<()>;
+}
+
+#[proxy(
+    interface = "org.freedesktop.Notifications",
+    default_service = "org.freedesktop.Notifications",
+    default_path = "/org/freedesktop/Notifications"
+)]
+trait Notifications {
+    #[allow(clippy::too_many_arguments)]
+    fn notify(
+        &self,
+        app_name: &str,
+        replaces_id: u32,
+        app_icon: &str,
+        summary: &str,
+        body: &str,
+        actions: &[&str],
+        hints: HashMap<&str, &Value<'_>>,
+        expire_timeout: i32,
+    ) -> zbus::Result<u32>;
+
+    #[zbus(signal)]
+    fn action_invoked(&self, id: u32, action_key: &str) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    fn notification_closed(&self, id: u32, reason: u32) -> zbus::Result<()>;
+}
+
+/// A way of showing short messages about what happened.
+pub trait Osd {
+    /// Returns the notification ID when `actions` can be invoked on the message.
+    fn show<'a>(
+        &'a self,
+        icon: &'a str,
+        text: &'a str,
+        actions: &'a [&'a str],
+    ) -> LocalBoxFuture<'a, Result<Option<u32>, Box<dyn std::error::Error>>>;
+}
+
+/// Plasma's OSD, which can't show actions.
+impl Osd for OsdServiceProxy<'_> {
+    fn show<'a>(
+        &'a self,
+        icon: &'a str,
+        text: &'a str,
+        _actions: &'a [&'a str],
+    ) -> LocalBoxFuture<'a, Result<Option<u32>, Box<dyn std::error::Error>>> {
+        Box::pin(async move {
+            self.show_text(icon, text).await?;
+            Ok(None)
+        })
+    }
+}
+
+impl Osd for NotificationsProxy<'_> {
+    fn show<'a>(
+        &'a self,
+        icon: &'a str,
+        text: &'a str,
+        actions: &'a [&'a str],
+    ) -> LocalBoxFuture<'a, Result<Option<u32>, Box<dyn std::error::Error>>> {
+        Box::pin(async move {
+            let desktop_entry = Value::from(dbus::SERVICE_NAME);
+            let id = self
+                .notify(
+                    "TrayPlay",
+                    0,
+                    icon,
+                    "TrayPlay",
+                    text,
+                    actions,
+                    HashMap::from([("desktop-entry", &desktop_entry)]),
+                    -1,
+                )
+                .await?;
+
+            Ok(Some(id))
+        })
+    }
+}
+
+impl Osd for Toast {
+    fn show<'a>(
+        &'a self,
+        icon: &'a str,
+        text: &'a str,
+        _actions: &'a [&'a str],
+    ) -> LocalBoxFuture<'a, Result<Option<u32>, Box<dyn std::error::Error>>> {
+        Toast::show(self, icon, text);
+        Box::pin(async { Ok(None) })
+    }
+}
+
+/// What a message is about, each can go to a different [`FeedbackBackend`].
+#[derive(Debug, Clone, Copy)]
+enum FeedbackEvent {
+    Saved,
+    Marked,
+    Started,
+    Stopped,
+    Error,
+}
+
+/// Shows short messages about what happened (replay saved, recording toggled) using
+/// Plasma OSD, desktop notifications or TrayPlay's own overlay, falling back to the
+/// others when the preferred service isn't running.
+pub struct Feedback<'a> {
+    backend: FeedbackBackend,
+    events: FeedbackEvents,
+    osd_service: Box<dyn Osd + 'a>,
+    notifications: Box<dyn Osd + 'a>,
+    overlay: Box<dyn Osd + 'a>,
+    /// Replays shown in notifications that are still open, so that their actions know
+    /// which file to act on.
+    notified_replays: Arc<Mutex<HashMap<u32, PathBuf>>>,
+}
+
+impl<'a> Feedback<'a> {
+    pub async fn new(
+        connection: &Connection,
+        backend: FeedbackBackend,
+        events: FeedbackEvents,
+        overlay: Toast,
+        action_event_tx: Sender<ActionEvent>,
+    ) -> zbus::Result<Self> {
+        let notifications = NotificationsProxy::new(connection).await?;
+        let mut action_invoked = notifications.receive_action_invoked().await?;
+        let mut notification_closed = notifications.receive_notification_closed().await?;
+
+        let feedback = Self::with_osds(
+            backend,
+            events,
+            Box::new(OsdServiceProxy::new(connection).await?),
+            Box::new(notifications),
+            Box::new(overlay),
+        );
+
+        let notified_replays = feedback.notified_replays.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    Some(signal) = action_invoked.next() => {
+                        let Ok(args) = signal.args() else { continue };
+                        let Some(path) = notified_replays.lock().await.get(&args.id).cloned() else {
+                            continue;
+                        };
+
+                        let event = match args.action_key {
+                            "default" | "open" => ActionEvent::OpenReplay(path),
+                            "show-in-folder" => ActionEvent::ShowReplayInFolder(path),
+                            "trash" => ActionEvent::TrashReplay(path),
+                            _ => continue,
+                        };
+                        let _ = action_event_tx.send(event).await;
+                    }
+                    Some(signal) = notification_closed.next() => {
+                        if let Ok(args) = signal.args() {
+                            notified_replays.lock().await.remove(&args.id);
+                        }
+                    }
+                    else => break,
+                }
+            }
+        });
+
+        Ok(feedback)
+    }
+
+    /// Shows messages with the given backends instead of the ones on the session bus.
+    pub fn with_osds(
+        backend: FeedbackBackend,
+        events: FeedbackEvents,
+        osd_service: Box<dyn Osd + 'a>,
+        notifications: Box<dyn Osd + 'a>,
+        overlay: Box<dyn Osd + 'a>,
+    ) -> Self {
+        Self {
+            backend,
+            events,
+            osd_service,
+            notifications,
+            overlay,
+            notified_replays: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub async fn replay_saved(&self, app_name: &str, path: &Path) {
+        let text = format!("Replay from \"{}\" saved!", app_name);
+        self.show_replay(&text, path).await;
+    }
+
+    pub async fn replay_converted(&self, profile_name: &str, path: &Path) {
+        let text = format!("Replay converted to {}", profile_name);
+        self.show_replay(&text, path).await;
+    }
+
+    /// Shows a message with actions for the replay at `path`.
+    async fn show_replay(&self, text: &str, path: &Path) {
+        let id = self
+            .show(
+                FeedbackEvent::Saved,
+                "media-record",
+                text,
+                &[
+                    "default",
+                    "Open",
+                    "open",
+                    "Open",
+                    "show-in-folder",
+                    "Show in folder",
+                    "trash",
+                    "Move to trash",
+                ],
+            )
+            .await;
+
+        if let Some(id) = id {
+            self.notified_replays
+                .lock()
+                .await
+                .insert(id, path.to_path_buf());
+        }
+    }
+
+    pub async fn moment_marked(&self) {
+        self.show(FeedbackEvent::Marked, "bookmark-new", "Moment marked", &[])
+            .await;
+    }
+
+    pub async fn recording_started(&self) {
+        self.show(
+            FeedbackEvent::Started,
+            "media-playback-playing",
+            "Replay recording started",
+            &[],
+        )
+        .await;
+    }
+
+    pub async fn recording_stopped(&self) {
+        self.show(
+            FeedbackEvent::Stopped,
+            "media-playback-stopped",
+            "Replay recording stopped",
+            &[],
+        )
+        .await;
+    }
+
+    /// Shows an error unless errors are configured to be shown in dialogs, in which case
+    /// `false` is returned. Errors are never hidden, so `none` means dialogs too.
+    pub async fn error(&self, text: &str) -> bool {
+        if matches!(self.events.error, None | Some(FeedbackBackend::None)) {
+            return false;
+        }
+
+        self.show(FeedbackEvent::Error, "dialog-error", text, &[])
+            .await;
+        true
+    }
+
+    fn osd(&self, backend: FeedbackBackend) -> Option<&dyn Osd> {
+        match backend {
+            FeedbackBackend::Osd => Some(self.osd_service.as_ref()),
+            FeedbackBackend::Notifications => Some(self.notifications.as_ref()),
+            FeedbackBackend::Overlay => Some(self.overlay.as_ref()),
+            FeedbackBackend::None => None,
+        }
+    }
+
+    /// Returns notification id if the message ended up being shown as a notification.
+    async fn show(
+        &self,
+        event: FeedbackEvent,
+        icon: &str,
+        text: &str,
+        actions: &[&str],
+    ) -> Option<u32> {
+        let backend = match event {
+            FeedbackEvent::Saved => self.events.saved,
+            FeedbackEvent::Marked => self.events.marked,
+            FeedbackEvent::Started => self.events.started,
+            FeedbackEvent::Stopped => self.events.stopped,
+            FeedbackEvent::Error => self.events.error,
+        }
+        .unwrap_or(self.backend);
+
+        // The overlay works everywhere, so it's the last resort
+        let order: &[FeedbackBackend] = match backend {
+            FeedbackBackend::Osd => &[
+                FeedbackBackend::Osd,
+                FeedbackBackend::Notifications,
+                FeedbackBackend::Overlay,
+            ],
+            FeedbackBackend::Notifications => &[
+                FeedbackBackend::Notifications,
+                FeedbackBackend::Osd,
+                FeedbackBackend::Overlay,
+            ],
+            FeedbackBackend::Overlay => &[FeedbackBackend::Overlay],
+            FeedbackBackend::None => &[],
+        };
+
+        for backend in order {
+            let Some(osd) = self.osd(*backend) else {
+                continue;
+            };
+
+            match osd.show(icon, text, actions).await {
+                Ok(id) => return id,
+                Err(err) => debug!("Cannot show {:?} feedback: {}", backend, err),
+            }
+        }
+
+        None
+    }
+}
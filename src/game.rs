@@ -0,0 +1,391 @@
+use std::path::{Path, PathBuf};
+
+use log::debug;
+use serde_json::Value;
+use tokio::fs;
+
+use crate::active_window::ActiveWindow;
+
+/// Wine loader executables, the actual game is in their command line.
+const WINE_LOADERS: [&str; 4] = ["wine-preloader", "wine64-preloader", "wine", "wine64"];
+
+/// Environment of a game process that identifies it.
+#[derive(Debug, Default)]
+pub struct GameEnvironment {
+    pub steam_app_id: Option<u32>,
+    /// Set by Lutris for everything it launches
+    pub lutris_game_name: Option<String>,
+    /// Store ID of a game launched by Heroic and the store it's from, e.g. `legendary`
+    pub heroic_app: Option<(String, String)>,
+    /// Bottle a program was started from by Bottles
+    pub bottle_name: Option<String>,
+}
+
+impl GameEnvironment {
+    pub async fn read(pid: i32) -> Self {
+        let Ok(environ) = fs::read(format!("/proc/{}/environ", pid)).await else {
+            return Self::default();
+        };
+
+        let mut environment = Self::default();
+        let mut heroic_app_name = None;
+        let mut heroic_app_source = None;
+        for (name, value) in environ
+            .split(|byte| *byte == 0)
+            .filter_map(|variable| std::str::from_utf8(variable).ok())
+            .filter_map(|variable| variable.split_once('='))
+        {
+            match name {
+                // Steam passes the app id of the game to everything it launches
+                "SteamAppId" | "STEAM_COMPAT_APP_ID" | "SteamGameId" => {
+                    if let Ok(app_id) = value.parse()
+                        && app_id != 0
+                    {
+                        environment.steam_app_id.get_or_insert(app_id);
+                    }
+                }
+                "GAME_NAME" if !value.is_empty() => {
+                    environment.lutris_game_name = Some(value.to_string());
+                }
+                "HEROIC_APP_NAME" if !value.is_empty() => heroic_app_name = Some(value),
+                "HEROIC_APP_SOURCE" => heroic_app_source = Some(value),
+                "BOTTLE_NAME" if !value.is_empty() => {
+                    environment.bottle_name = Some(value.to_string());
+                }
+                _ => {}
+            }
+        }
+
+        if let Some(app_name) = heroic_app_name {
+            environment.heroic_app = Some((
+                app_name.to_string(),
+                heroic_app_source.unwrap_or_default().to_string(),
+            ));
+        }
+
+        environment
+    }
+}
+
+/// Name of the game running in `window`, if it was started by a launcher we know or Wine.
+pub async fn game_name(
+    window: &ActiveWindow,
+    environment: &GameEnvironment,
+    steam_library_folders: &[PathBuf],
+) -> Option<String> {
+    if let Some(app_id) = environment.steam_app_id {
+        let name = steam_game_name(app_id, steam_library_folders).await;
+        if name.is_some() {
+            return name;
+        }
+        debug!("Steam app {} isn't installed in any known library", app_id);
+    }
+
+    if let Some(name) = &environment.lutris_game_name {
+        return Some(name.clone());
+    }
+
+    if let Some((app_name, source)) = &environment.heroic_app {
+        let name = heroic_game_name(app_name, source).await;
+        if name.is_some() {
+            return name;
+        }
+        debug!("Heroic game {} from {} isn't installed", app_name, source);
+    }
+
+    let windows_file_name = wine_file_name(window).await?;
+
+    if let Some(bottle_name) = &environment.bottle_name {
+        let name = bottles_program_name(bottle_name, &windows_file_name).await;
+        if name.is_some() {
+            return name;
+        }
+        debug!(
+            "{} isn't a program of bottle {}",
+            windows_file_name, bottle_name
+        );
+    }
+
+    // Everything else running through plain Wine is named after its executable
+    Some(
+        Path::new(&windows_file_name)
+            .file_stem()?
+            .to_string_lossy()
+            .into_owned(),
+    )
+}
+
+/// Heroic's data of native and Flatpak installations.
+fn heroic_config_roots() -> Vec<PathBuf> {
+    let Some(config_dir) = dirs::config_dir() else {
+        return vec![];
+    };
+    let Some(home) = dirs::home_dir() else {
+        return vec![config_dir.join("heroic")];
+    };
+
+    vec![
+        config_dir.join("heroic"),
+        home.join(".var/app/com.heroicgameslauncher.hgl/config/heroic"),
+    ]
+}
+
+/// Files in Heroic's config listing games of `source` with their titles.
+fn heroic_libraries(source: &str) -> &'static [&'static str] {
+    match source {
+        "legendary" => &["legendaryConfig/legendary/installed.json"],
+        "gog" => &["store_cache/gog_library.json", "gog_store/library.json"],
+        "nile" => &["store_cache/nile_library.json"],
+        "sideload" => &["sideload_apps/library.json"],
+        // Older Heroic versions don't say
+        _ => &[
+            "legendaryConfig/legendary/installed.json",
+            "store_cache/gog_library.json",
+            "gog_store/library.json",
+            "store_cache/nile_library.json",
+            "sideload_apps/library.json",
+        ],
+    }
+}
+
+async fn heroic_game_name(app_name: &str, source: &str) -> Option<String> {
+    for root in heroic_config_roots() {
+        for library in heroic_libraries(source) {
+            let Ok(content) = fs::read_to_string(root.join(library)).await else {
+                continue;
+            };
+            let Ok(library) = serde_json::from_str::<Value>(&content) else {
+                debug!("Cannot parse Heroic library {}", library);
+                continue;
+            };
+
+            if let Some(title) = heroic_title(&library, app_name) {
+                return Some(title);
+            }
+        }
+    }
+
+    None
+}
+
+/// Title of the game with the store ID `app_name`, wherever it is in a Heroic library.
+/// Every store's file is laid out differently, but games always have both fields.
+fn heroic_title(library: &Value, app_name: &str) -> Option<String> {
+    match library {
+        Value::Object(object) => {
+            let id = object.get("app_name").or_else(|| object.get("appName"));
+            if id.and_then(Value::as_str) == Some(app_name)
+                && let Some(title) = object.get("title").and_then(Value::as_str)
+            {
+                return Some(title.to_string());
+            }
+
+            object
+                .values()
+                .find_map(|value| heroic_title(value, app_name))
+        }
+        Value::Array(values) => values
+            .iter()
+            .find_map(|value| heroic_title(value, app_name)),
+        _ => None,
+    }
+}
+
+async fn bottles_program_name(bottle_name: &str, windows_file_name: &str) -> Option<String> {
+    let data_dir = dirs::data_dir()?;
+    let home = dirs::home_dir()?;
+
+    for bottles in [
+        data_dir.join("bottles/bottles"),
+        home.join(".var/app/com.usebottles.bottles/data/bottles/bottles"),
+    ] {
+        if let Ok(config) = fs::read_to_string(bottles.join(bottle_name).join("bottle.yml")).await
+            && let Some(name) = bottle_program(&config, windows_file_name)
+        {
+            return Some(name);
+        }
+    }
+
+    None
+}
+
+/// Name the user gave the program in a bottle that runs `windows_file_name`. Only reads
+/// `External_Programs` of `bottle.yml`, which lists programs by ID with their `name` and
+/// Windows `path`.
+fn bottle_program(config: &str, windows_file_name: &str) -> Option<String> {
+    let mut in_programs = false;
+    let mut name: Option<String> = None;
+    let mut path: Option<String> = None;
+
+    for line in config.lines().chain(std::iter::once("End:")) {
+        let indent = line.len() - line.trim_start().len();
+        let is_key_only = line.trim_end().ends_with(':');
+
+        // A new program or section starts, check the one before
+        if (indent == 0 || (indent == 2 && is_key_only))
+            && let (Some(name), Some(path)) = (name.take(), path.take())
+            && in_programs
+            && file_name(&path).eq_ignore_ascii_case(windows_file_name)
+        {
+            return Some(name);
+        }
+
+        if indent == 0 {
+            in_programs = line.trim_end() == "External_Programs:";
+            name = None;
+            path = None;
+        } else if in_programs {
+            let line = line.trim();
+            if let Some(value) = line.strip_prefix("name:") {
+                name = Some(yaml_string(value));
+            } else if let Some(value) = line.strip_prefix("path:") {
+                path = Some(yaml_string(value));
+            }
+        }
+    }
+
+    None
+}
+
+fn yaml_string(value: &str) -> String {
+    value.trim().trim_matches(['\'', '"']).to_string()
+}
+
+fn file_name(windows_path: &str) -> &str {
+    windows_path.rsplit(['\\', '/']).next().unwrap_or_default()
+}
+
+async fn steam_game_name(app_id: u32, library_folders: &[PathBuf]) -> Option<String> {
+    let library_folders = if library_folders.is_empty() {
+        detect_steam_libraries().await
+    } else {
+        library_folders.to_vec()
+    };
+
+    for library in library_folders {
+        let manifest = library
+            .join("steamapps")
+            .join(format!("appmanifest_{}.acf", app_id));
+        if let Ok(content) = fs::read_to_string(&manifest).await
+            && let Some(name) = vdf_values(&content, "name").next()
+        {
+            return Some(name.to_string());
+        }
+    }
+
+    None
+}
+
+/// Libraries listed by native and Flatpak Steam installations.
+async fn detect_steam_libraries() -> Vec<PathBuf> {
+    let Some(home) = dirs::home_dir() else {
+        return vec![];
+    };
+
+    let steam_roots = [
+        home.join(".local/share/Steam"),
+        home.join(".steam/steam"),
+        home.join(".var/app/com.valvesoftware.Steam/.local/share/Steam"),
+    ];
+
+    let mut libraries = vec![];
+    for root in steam_roots {
+        let Ok(content) = fs::read_to_string(root.join("steamapps/libraryfolders.vdf")).await
+        else {
+            continue;
+        };
+
+        for path in vdf_values(&content, "path").map(PathBuf::from) {
+            if !libraries.contains(&path) {
+                libraries.push(path);
+            }
+        }
+    }
+
+    libraries
+}
+
+/// Values of `key` in Valve's KeyValues format, e.g. `"name"  "Half-Life 2"`.
+fn vdf_values<'a>(content: &'a str, key: &'a str) -> impl Iterator<Item = &'a str> {
+    content.lines().filter_map(move |line| {
+        let mut tokens = line
+            .trim()
+            .split('"')
+            .filter(|token| !token.trim().is_empty());
+        (tokens.next()? == key).then(|| tokens.next()).flatten()
+    })
+}
+
+/// File name of the Windows program `window` belongs to, if it runs through Wine.
+async fn wine_file_name(window: &ActiveWindow) -> Option<String> {
+    let exe_name = window.exe.as_deref()?.file_name()?.to_str()?;
+    if !WINE_LOADERS.contains(&exe_name) {
+        return None;
+    }
+
+    let cmdline = fs::read_to_string(format!("/proc/{}/cmdline", window.pid))
+        .await
+        .ok()?;
+    // Wine rewrites its command line to the Windows path of the game
+    let windows_path = cmdline.split('\0').next()?;
+    Some(file_name(windows_path).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn heroic_titles() {
+        // legendary's installed.json is keyed by app name
+        let legendary = json!({
+            "Fortnite": { "app_name": "Fortnite", "title": "Fortnite" },
+            "Sugar": { "app_name": "Sugar", "title": "Rocket League" },
+        });
+        assert_eq!(
+            heroic_title(&legendary, "Sugar").as_deref(),
+            Some("Rocket League")
+        );
+
+        // The store caches are lists
+        let gog = json!({ "games": [
+            { "appName": "1207658924", "title": "Unreal Tournament 2004" },
+            { "app_name": "1207664643", "title": "Heroes of Might and Magic 3" },
+        ]});
+        assert_eq!(
+            heroic_title(&gog, "1207664643").as_deref(),
+            Some("Heroes of Might and Magic 3")
+        );
+        assert_eq!(heroic_title(&gog, "Sugar"), None);
+    }
+
+    #[test]
+    fn bottle_programs() {
+        let config = "\
+Arch: win64
+External_Programs:
+  0f5b3c1e-1111:
+    arguments: ''
+    executable: Launcher.exe
+    name: Game Launcher
+    path: C:\\Program Files\\Game\\Launcher.exe
+  7a8b9c0d-2222:
+    name: 'Deus Ex'
+    path: 'C:\\GOG Games\\Deus Ex\\System\\DeusEx.exe'
+Installed_Dependencies: []
+Name: Games
+";
+
+        assert_eq!(
+            bottle_program(config, "deusex.exe").as_deref(),
+            Some("Deus Ex")
+        );
+        assert_eq!(
+            bottle_program(config, "Launcher.exe").as_deref(),
+            Some("Game Launcher")
+        );
+        assert_eq!(bottle_program(config, "Other.exe"), None);
+    }
+}
@@ -5,16 +5,29 @@ use std::{
     process::{Child, Command, Stdio},
     str::FromStr,
     sync::Arc,
+    time::Duration,
 };
 
-use log::debug;
+use log::{debug, error, warn};
 use nix::{
     sys::signal::{self, Signal},
     unistd::Pid,
 };
-use tokio::{sync::RwLock, task::JoinHandle};
+use tokio::{
+    sync::{RwLock, mpsc::Sender},
+    task::JoinHandle,
+};
 
-use crate::{config::Config, utils::process_pattern};
+use crate::{
+    ActionEvent,
+    active_window::WindowHistory,
+    config::Config,
+    container::{ContainerMetadata, RemuxLocks, write_metadata},
+    hooks::{HookContext, run_post_save_hooks},
+    markers::Markers,
+    metadata::ReplayMetadata,
+    utils::{free_space, process_pattern},
+};
 
 #[allow(dead_code)]
 #[derive(Debug)]
@@ -22,6 +35,8 @@ pub enum Error {
     IoError(std::io::Error),
     NixErrno(nix::errno::Errno),
     RecorderNotRunning,
+    /// Free space in MB left where the replay would be written
+    LowDiskSpace(PathBuf, u64),
 }
 
 impl Display for Error {
@@ -47,26 +62,42 @@ impl From<nix::errno::Errno> for Error {
 pub struct GpuScreenRecorder {
     process: Option<Child>,
     config: Arc<RwLock<Config>>,
-    app_name: Arc<RwLock<String>>,
+    window_history: Arc<RwLock<WindowHistory>>,
+    action_event_tx: Sender<ActionEvent>,
+    markers: Arc<RwLock<Markers>>,
+    remux_locks: RemuxLocks,
     stdout_task_handle: Option<JoinHandle<()>>,
     stderr_task_handle: Option<JoinHandle<()>>,
+    low_disk_space_warned: bool,
 }
 
 impl GpuScreenRecorder {
     pub async fn new(
         config: Arc<RwLock<Config>>,
-        app_name: Arc<RwLock<String>>,
+        window_history: Arc<RwLock<WindowHistory>>,
+        action_event_tx: Sender<ActionEvent>,
+        remux_locks: RemuxLocks,
     ) -> Result<Self, Error> {
         Ok(Self {
             process: None,
             config,
-            app_name,
+            window_history,
+            action_event_tx,
+            markers: Arc::new(RwLock::new(Markers::default())),
+            remux_locks,
             stderr_task_handle: None,
             stdout_task_handle: None,
+            low_disk_space_warned: false,
         })
     }
 
     pub async fn start(&mut self) -> Result<(), Error> {
+        // Recording is still useful if there's room to free up before saving
+        match self.check_disk_space().await {
+            Err(Error::LowDiskSpace(_, _)) => {}
+            result => result?,
+        }
+
         let config = self.config.read().await;
 
         let mut process = Command::new("gpu-screen-recorder")
@@ -106,8 +137,11 @@ impl GpuScreenRecorder {
         }));
 
         let stdout = process.stdout.take().unwrap();
-        let app_name_clone = self.app_name.clone();
+        let window_history = self.window_history.clone();
         let config_clone = self.config.clone();
+        let action_event_tx = self.action_event_tx.clone();
+        let markers = self.markers.clone();
+        let remux_locks = self.remux_locks.clone();
         self.stdout_task_handle = Some(tokio::spawn(async move {
             let reader = BufReader::new(stdout);
             for line in reader.lines().filter_map(|line| line.ok()) {
@@ -116,7 +150,20 @@ impl GpuScreenRecorder {
                 let path = PathBuf::from_str(&line)
                     .expect("gpu-screen-recorder stdout must only contain file paths");
 
-                let app_name = app_name_clone.read().await.clone();
+                let replay_duration =
+                    Duration::from_secs(config.replay_duration_secs.max(0) as u64);
+                let chapters = markers.write().await.take_pending();
+                let replay_length = if chapters.replay_length.is_zero() {
+                    replay_duration
+                } else {
+                    chapters.replay_length
+                };
+
+                let active_window = window_history
+                    .read()
+                    .await
+                    .attribute(config.replay_attribution, replay_length);
+                let app_name = active_window.app_name.clone();
 
                 let target_path = format!(
                     "{}/{}.{}",
@@ -129,22 +176,103 @@ impl GpuScreenRecorder {
                 let mut target_path_dir = target_path.clone();
                 target_path_dir.pop(); // Remove file name
 
-                std::fs::create_dir_all(target_path_dir.join("/"))
-                    .expect("failed to create directories");
+                let target_path = PathBuf::from(target_path.join("/"));
+                let relocated = std::fs::create_dir_all(target_path_dir.join("/"))
+                    .and_then(|_| std::fs::rename(&path, &target_path));
+                // Keep the replay where gpu-screen-recorder saved it rather than losing it
+                let target_path = match relocated {
+                    Ok(()) => target_path,
+                    Err(err) => {
+                        error!(
+                            "Cannot move replay to {}, it was left at {}: {}",
+                            target_path.display(),
+                            path.display(),
+                            err
+                        );
+                        path
+                    }
+                };
+
+                let metadata = config
+                    .metadata_sidecar
+                    .then(|| ReplayMetadata::new(&config, &active_window));
+                let hooks = config.post_save_hooks.clone();
+                let hook_timeout = Duration::from_secs(config.post_save_hook_timeout_secs);
+                let mut container_metadata = ContainerMetadata {
+                    chapters,
+                    ..Default::default()
+                };
+                if config.embed_tags {
+                    container_metadata.tag_replay(&active_window);
+                }
+                drop(config);
+
+                let mut metadata_file = None;
+                if let Some(metadata) = metadata {
+                    match metadata.save(&target_path).await {
+                        Ok(()) => metadata_file = Some(ReplayMetadata::sidecar_path(&target_path)),
+                        Err(err) => {
+                            warn!("Cannot save metadata of {}: {}", target_path.display(), err)
+                        }
+                    }
+                }
+
+                // Remuxing takes a while for long replays, don't hold up feedback and later
+                // saves. Whatever touches the file before it's done waits for the lock, hooks
+                // get it once its metadata is in place.
+                let remux_guard = remux_locks.lock(&target_path);
+                let hook_context = HookContext {
+                    file: target_path.clone(),
+                    app_name: app_name.clone(),
+                    window_title: active_window.title.clone(),
+                    duration: replay_length,
+                    metadata_file,
+                };
+                tokio::spawn(async move {
+                    if let Err(err) = write_metadata(&hook_context.file, &container_metadata).await
+                    {
+                        warn!(
+                            "Cannot embed metadata into {}: {}",
+                            hook_context.file.display(),
+                            err
+                        );
+                    }
+                    drop(remux_guard);
+
+                    run_post_save_hooks(hooks, hook_timeout, hook_context);
+                });
 
-                std::fs::rename(path, target_path.join("/")).expect("failed to move replay");
+                let _ = action_event_tx
+                    .send(ActionEvent::ReplaySaved(target_path, app_name))
+                    .await;
             }
         }));
 
         self.process = Some(process);
+        self.markers.write().await.recorder_started();
 
         Ok(())
     }
 
     pub async fn stop(&mut self) -> Result<(), Error> {
+        self.stop_process().await?;
+        self.markers.write().await.session_ended();
+
+        Ok(())
+    }
+
+    /// Starts again with the current config. Unlike [`Self::stop`], this keeps chapters of
+    /// replays that are still being saved.
+    pub async fn restart(&mut self) -> Result<(), Error> {
+        self.stop_process().await?;
+        self.start().await
+    }
+
+    async fn stop_process(&mut self) -> Result<(), Error> {
         if let Some(process) = &self.process {
             signal::kill(Pid::from_raw(process.id() as i32), Signal::SIGTERM)?;
             self.process = None;
+            self.markers.write().await.recorder_stopped();
 
             Ok(())
         } else {
@@ -153,9 +281,60 @@ impl GpuScreenRecorder {
     }
 
     pub async fn save_replay(&mut self) -> Result<(), Error> {
-        // info!("Saving replay from {}", self.app_name.read().await);
-        if let Some(process) = &self.process {
-            signal::kill(Pid::from_raw(process.id() as i32), Signal::SIGUSR1)?;
+        if let Some(pid) = self.process.as_ref().map(|process| process.id()) {
+            // Saving with a full disk would only produce a truncated file
+            self.check_disk_space().await?;
+
+            signal::kill(Pid::from_raw(pid as i32), Signal::SIGUSR1)?;
+
+            let config = self.config.read().await;
+            self.markers.write().await.replay_requested(
+                Duration::from_secs(config.replay_duration_secs.max(0) as u64),
+                config.clear_buffer_on_save,
+            );
+
+            Ok(())
+        } else {
+            Err(Error::RecorderNotRunning)
+        }
+    }
+
+    /// Fails when there's less than `disk_space_minimum_mb` free in the replay directory,
+    /// which gpu-screen-recorder writes to directly. Warns when free space drops below
+    /// `disk_space_warning_mb`, only once until there's enough again, as warnings show a
+    /// dialog.
+    async fn check_disk_space(&mut self) -> Result<(), Error> {
+        let config = self.config.read().await;
+        let directory = config.replay_directory.clone();
+        let free_mb = free_space(&directory)? / 1024 / 1024;
+        let too_low = free_mb < config.disk_space_minimum_mb;
+        let low = free_mb < config.disk_space_warning_mb;
+        drop(config);
+
+        if low && !self.low_disk_space_warned {
+            warn!(
+                "Only {} MB of free space left in {}{}",
+                free_mb,
+                directory.display(),
+                if too_low {
+                    ", replays won't be saved until you free some up"
+                } else {
+                    ""
+                }
+            );
+        }
+        self.low_disk_space_warned = low;
+
+        if too_low {
+            return Err(Error::LowDiskSpace(directory, free_mb));
+        }
+
+        Ok(())
+    }
+
+    /// Marks the current moment so it becomes a chapter of the next saved replay.
+    pub async fn mark_moment(&self) -> Result<(), Error> {
+        if self.markers.write().await.mark() {
             Ok(())
         } else {
             Err(Error::RecorderNotRunning)
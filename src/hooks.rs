@@ -0,0 +1,124 @@
+use std::{
+    path::{Path, PathBuf},
+    process::Stdio,
+    time::Duration,
+};
+
+use log::{error, info};
+use tokio::process::Command;
+
+use crate::utils::process_pattern;
+
+/// What post-save hooks get to know about the replay, passed as `TRAYPLAY_*` environment
+/// variables.
+#[derive(Debug, Clone)]
+pub struct HookContext {
+    pub file: PathBuf,
+    pub app_name: String,
+    pub window_title: String,
+    pub duration: Duration,
+    pub metadata_file: Option<PathBuf>,
+}
+
+/// Runs `hooks` one after another in the background, so a hook can rely on the previous
+/// one (e.g. transcode, then upload). A failed hook stops the rest.
+pub fn run_post_save_hooks(hooks: Vec<String>, timeout: Duration, context: HookContext) {
+    if hooks.is_empty() {
+        return;
+    }
+
+    tokio::spawn(async move {
+        for hook in hooks {
+            let command = expand_hook(&hook);
+            if let Err(err) = run_hook(&command, timeout, &context).await {
+                error!("Post-save hook `{}` failed: {}", command, err);
+                break;
+            }
+        }
+    });
+}
+
+/// What `%app%` in a hook turns into. App names come from window titles, so they're never
+/// pasted into the command: a variable reference can't inject shell code, quoted or not.
+const APP_PLACEHOLDER_VALUE: &str = "\"$TRAYPLAY_APP\"";
+
+/// Fills in the same placeholders as `file_name_pattern`.
+fn expand_hook(hook: &str) -> String {
+    process_pattern(hook, "%app%").replace("%app%", APP_PLACEHOLDER_VALUE)
+}
+
+async fn run_hook(command: &str, timeout: Duration, context: &HookContext) -> Result<(), String> {
+    info!("Running post-save hook `{}`", command);
+
+    let child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .env("TRAYPLAY_FILE", &context.file)
+        .env(
+            "TRAYPLAY_DIRECTORY",
+            context.file.parent().unwrap_or(Path::new("/")),
+        )
+        .env("TRAYPLAY_APP", &context.app_name)
+        .env("TRAYPLAY_WINDOW_TITLE", &context.window_title)
+        .env("TRAYPLAY_DURATION", context.duration.as_secs().to_string())
+        .env(
+            "TRAYPLAY_METADATA",
+            context.metadata_file.as_deref().unwrap_or(Path::new("")),
+        )
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|err| err.to_string())?;
+
+    let output = tokio::time::timeout(timeout, child.wait_with_output())
+        .await
+        .map_err(|_| format!("timed out after {} seconds", timeout.as_secs()))?
+        .map_err(|err| err.to_string())?;
+
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        info!(target: "post-save hook stdout", "{}", line);
+    }
+    for line in String::from_utf8_lossy(&output.stderr).lines() {
+        info!(target: "post-save hook stderr", "{}", line);
+    }
+
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(format!("exited with {}", output.status))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(command: &str, app_name: &str) -> String {
+        let output = std::process::Command::new("sh")
+            .arg("-c")
+            .arg(command.replace("%app%", APP_PLACEHOLDER_VALUE))
+            .env("TRAYPLAY_APP", app_name)
+            .output()
+            .unwrap();
+
+        assert!(output.status.success(), "{}", command);
+        String::from_utf8_lossy(&output.stdout).to_string()
+    }
+
+    #[test]
+    fn app_name_cannot_inject_commands() {
+        for app_name in [
+            "Half-Life 2",
+            "it's $(printf INJ; printf ECTED)",
+            "`printf INJ; printf ECTED`",
+            "\"; printf INJ''ECTED; \"",
+            "",
+        ] {
+            assert_eq!(run("printf %s %app%", app_name), app_name);
+            // Quoting it again splits words, but still doesn't run anything
+            assert!(!run("printf %s \"%app%\"", app_name).contains("INJECTED"));
+        }
+    }
+}
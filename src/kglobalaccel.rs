@@ -0,0 +1,246 @@
+use std::process::Command;
+
+use futures_util::StreamExt;
+use log::{info, warn};
+use tokio::sync::{
+    mpsc::{self, Sender},
+    watch,
+};
+use zbus::{Connection, proxy, zvariant::OwnedObjectPath};
+
+use crate::{ActionEvent, config::Shortcut, shortcuts::BoundShortcut};
+
+const COMPONENT_UNIQUE: &str = "trayplay";
+const COMPONENT_FRIENDLY: &str = "TrayPlay";
+
+// Flags accepted by setShortcut, from KGlobalAccel::SetShortcutFlag. Without NoAutoloading
+// (4) a trigger the user already configured is kept.
+const SET_PRESENT: u32 = 2;
+const IS_DEFAULT: u32 = 8;
+
+#[proxy(
+    interface = "org.kde.KGlobalAccel",
+    default_service = "org.kde.kglobalaccel",
+    default_path = "/kglobalaccel"
+)]
+trait KGlobalAccel {
+    #[zbus(name = "doRegister")]
+    fn do_register(&self, action_id: &[&str]) -> zbus::Result<()>;
+
+    #[zbus(name = "setShortcut")]
+    fn set_shortcut(&self, action_id: &[&str], keys: &[i32], flags: u32) -> zbus::Result<Vec<i32>>;
+
+    #[zbus(name = "setInactive")]
+    fn set_inactive(&self, action_id: &[&str]) -> zbus::Result<()>;
+
+    #[zbus(name = "getComponent")]
+    fn get_component(&self, component_unique: &str) -> zbus::Result<OwnedObjectPath>;
+}
+
+#[proxy(
+    interface = "org.kde.kglobalaccel.Component",
+    default_service = "org.kde.kglobalaccel"
+)]
+trait KGlobalAccelComponent {
+    #[zbus(signal, name = "globalShortcutPressed")]
+    fn global_shortcut_pressed(
+        &self,
+        component_unique: &str,
+        shortcut_unique: &str,
+        timestamp: i64,
+    ) -> zbus::Result<()>;
+}
+
+/// Registers shortcuts directly with Plasma's shortcut daemon. Used when the
+/// GlobalShortcuts portal isn't available.
+pub struct KGlobalAccelShortcuts<'a> {
+    connection: Connection,
+    kglobalaccel_proxy: KGlobalAccelProxy<'a>,
+    shortcut_tx: Sender<ActionEvent>,
+    bound_shortcuts_tx: watch::Sender<Vec<BoundShortcut>>,
+    shortcuts: Vec<Shortcut>,
+}
+
+impl<'a> KGlobalAccelShortcuts<'a> {
+    pub async fn new(
+        shortcut_tx: Sender<ActionEvent>,
+        bound_shortcuts_tx: watch::Sender<Vec<BoundShortcut>>,
+        shortcuts: Vec<Shortcut>,
+    ) -> zbus::Result<Self> {
+        let connection = Connection::session().await?;
+        let kglobalaccel_proxy = KGlobalAccelProxy::new(&connection).await?;
+
+        // Fails early when kglobalaccel isn't running
+        kglobalaccel_proxy.inner().introspect().await?;
+
+        Ok(Self {
+            connection,
+            kglobalaccel_proxy,
+            shortcut_tx,
+            bound_shortcuts_tx,
+            shortcuts,
+        })
+    }
+
+    pub async fn register_all(&self) -> zbus::Result<()> {
+        let mut bound_shortcuts = vec![];
+
+        for shortcut in &self.shortcuts {
+            let action_id = action_id(shortcut);
+            let keys = shortcut
+                .trigger
+                .as_deref()
+                .and_then(parse_trigger)
+                .into_iter()
+                .collect::<Vec<i32>>();
+
+            self.kglobalaccel_proxy.do_register(&action_id).await?;
+            self.kglobalaccel_proxy
+                .set_shortcut(&action_id, &keys, IS_DEFAULT)
+                .await?;
+            // Keeps the trigger chosen by the user in system settings if there is one
+            let active_keys = self
+                .kglobalaccel_proxy
+                .set_shortcut(&action_id, &keys, SET_PRESENT)
+                .await?;
+
+            bound_shortcuts.push(BoundShortcut {
+                id: shortcut.id.clone(),
+                description: shortcut.description.clone(),
+                trigger: if active_keys == keys {
+                    shortcut.trigger.clone().unwrap_or_default()
+                } else {
+                    // Changed by the user, we can't describe Qt key codes nicely
+                    "Custom".to_string()
+                },
+            });
+        }
+
+        self.bound_shortcuts_tx.send_replace(bound_shortcuts);
+
+        Ok(())
+    }
+
+    /// There is no configuration dialog over D-Bus, so open the shortcuts page of
+    /// system settings instead.
+    fn configure(&self) {
+        if let Err(err) = Command::new("systemsettings").arg("kcm_keys").spawn() {
+            warn!("Cannot open shortcut settings: {}", err);
+        }
+    }
+
+    pub async fn unregister_all(&self) {
+        for shortcut in &self.shortcuts {
+            if let Err(err) = self
+                .kglobalaccel_proxy
+                .set_inactive(&action_id(shortcut))
+                .await
+            {
+                warn!("Cannot deactivate shortcut {}: {}", shortcut.id, err);
+            }
+        }
+    }
+
+    pub async fn run_handler(
+        &mut self,
+        mut shortcuts_rx: watch::Receiver<Vec<Shortcut>>,
+        configure_rx: &mut mpsc::Receiver<()>,
+    ) -> zbus::Result<()> {
+        let component_path = self
+            .kglobalaccel_proxy
+            .get_component(COMPONENT_UNIQUE)
+            .await?;
+        let component_proxy = KGlobalAccelComponentProxy::builder(&self.connection)
+            .path(component_path)?
+            .build()
+            .await?;
+        let mut pressed = component_proxy.receive_global_shortcut_pressed().await?;
+
+        loop {
+            tokio::select! {
+                Some(signal) = pressed.next() => {
+                    let Ok(args) = signal.args() else { continue };
+                    let event = self
+                        .shortcuts
+                        .iter()
+                        .find(|shortcut| shortcut.id == args.shortcut_unique)
+                        .map(|shortcut| ActionEvent::from(&shortcut.action))
+                        .unwrap_or(ActionEvent::Unknown);
+                    let _ = self.shortcut_tx.send(event).await;
+                }
+                Some(()) = configure_rx.recv() => self.configure(),
+                Ok(()) = shortcuts_rx.changed() => {
+                    info!("Shortcut list changed, registering shortcuts again");
+                    self.unregister_all().await;
+                    self.shortcuts = shortcuts_rx.borrow_and_update().clone();
+                    self.register_all().await?;
+                }
+                else => return Ok(()),
+            }
+        }
+    }
+}
+
+fn action_id(shortcut: &Shortcut) -> [&str; 4] {
+    [
+        COMPONENT_UNIQUE,
+        &shortcut.id,
+        COMPONENT_FRIENDLY,
+        &shortcut.description,
+    ]
+}
+
+/// Converts a trigger in the shortcuts spec format used by the portal (e.g. `ALT+SHIFT+F10`)
+/// into a Qt key code understood by kglobalaccel.
+fn parse_trigger(trigger: &str) -> Option<i32> {
+    let mut code = 0;
+    let mut key = None;
+
+    for part in trigger.split('+').map(|part| part.trim().to_uppercase()) {
+        match part.as_str() {
+            "SHIFT" => code |= 0x0200_0000,
+            "CTRL" | "CONTROL" => code |= 0x0400_0000,
+            "ALT" => code |= 0x0800_0000,
+            "LOGO" | "META" | "SUPER" => code |= 0x1000_0000,
+            other => key = Some(parse_key(other)?),
+        }
+    }
+
+    Some(code | key?)
+}
+
+fn parse_key(key: &str) -> Option<i32> {
+    if let Some(number) = key.strip_prefix('F').and_then(|n| n.parse::<i32>().ok()) {
+        return (1..=35)
+            .contains(&number)
+            .then_some(0x0100_0030 + number - 1);
+    }
+
+    let mut chars = key.chars();
+    if let (Some(char), None) = (chars.next(), chars.next())
+        && (char.is_ascii_alphanumeric() || char.is_ascii_punctuation())
+    {
+        return Some(char as i32);
+    }
+
+    Some(match key {
+        "ESCAPE" => 0x0100_0000,
+        "TAB" => 0x0100_0001,
+        "BACKSPACE" => 0x0100_0003,
+        "RETURN" | "ENTER" => 0x0100_0004,
+        "INSERT" => 0x0100_0006,
+        "DELETE" => 0x0100_0007,
+        "PAUSE" => 0x0100_0008,
+        "PRINT" => 0x0100_0009,
+        "HOME" => 0x0100_0010,
+        "END" => 0x0100_0011,
+        "LEFT" => 0x0100_0012,
+        "UP" => 0x0100_0013,
+        "RIGHT" => 0x0100_0014,
+        "DOWN" => 0x0100_0015,
+        "PAGE_UP" | "PAGEUP" => 0x0100_0016,
+        "PAGE_DOWN" | "PAGEDOWN" => 0x0100_0017,
+        "SPACE" => 0x20,
+        _ => return None,
+    })
+}
@@ -1,30 +1,48 @@
 #![recursion_limit = "256"]
 
-use std::{error::Error, str::FromStr, sync::Arc};
+use std::{error::Error, path::PathBuf, str::FromStr, sync::Arc};
 
+use active_window::WindowHistory;
+use app::{App, describe_start_error};
 use ashpd::{AppID, register_host_app};
-use config::Config;
+use config::{Config, Quality};
+use container::RemuxLocks;
+use feedback::Feedback;
 use gsr::GpuScreenRecorder;
-use ksni::TrayMethods;
-use kwin::KWinScriptManager;
-use log::{error, info, warn};
+use log::{error, info};
 use logger::{CombinedLogger, UiLogger};
-use tokio::sync::{RwLock, mpsc};
-use tray::TrayIcon;
-use utils::ask_path;
-use zbus::{Connection, names::BusName, proxy};
+use markers::MarkerService;
+use recent::RecentReplays;
+use shortcuts::{ShortcutChannels, ShortcutsStatus};
+use tokio::sync::{RwLock, mpsc, watch};
+use transcode::spawn_transcode_queue;
+use tray::spawn_tray;
+use zbus::{Connection, names::BusName};
 
 use crate::ui::Ui;
 
 mod active_window;
+mod app;
 mod config;
+mod container;
+mod dbus;
+mod desktop_entries;
+mod feedback;
+mod game;
 mod gsr;
-mod kwin;
+mod hooks;
+mod kglobalaccel;
 mod logger;
+mod markers;
+mod metadata;
+mod recent;
+mod retention;
 mod shortcuts;
+mod transcode;
 mod tray;
 mod ui;
 mod utils;
+mod window_source;
 
 #[derive(Debug)]
 pub enum ActionEvent {
@@ -37,16 +55,23 @@ pub enum ActionEvent {
     ShowWindow(String),
     ShowInfo(String, String),
     ShowError(String, String),
-}
-
-#[proxy(
-    interface = "org.kde.osdService",
-    default_service = "org.kde.plasmashell",
-    default_path = "/org/kde/osdService"
-)]
-trait OsdService {
-    #[zbus(name = "showText")]
-    fn show_text(&self, icon: &str, text: &str) -> zbus::Result<()>;
+    ReplaySaved(PathBuf, String),
+    OpenReplay(PathBuf),
+    ShowReplayInFolder(PathBuf),
+    CopyReplayPath(PathBuf),
+    TrashReplay(PathBuf),
+    SetFramerate(u32),
+    SetQuality(Quality),
+    SetReplayDuration(i64),
+    SetVideoSource(String),
+    ShortcutsStatusChanged,
+    ConfigureShortcuts,
+    MarkMoment,
+    Transcode(PathBuf, String),
+    TranscodeProgress(Option<String>),
+    TranscodeFinished(PathBuf, String, String),
+    ToggleKeepReplay(PathBuf),
+    ApplyRetention,
 }
 
 #[tokio::main]
@@ -69,13 +94,24 @@ async fn main() -> Result<(), Box<dyn Error>> {
 
     let config = Arc::new(RwLock::new(Config::load(action_tx.clone()).await));
 
-    let mut ui = Ui::new(action_tx.clone(), config.clone()).await;
+    let shortcuts_status = Arc::new(RwLock::new(ShortcutsStatus::Connecting));
+    let (bound_shortcuts_tx, bound_shortcuts_rx) = watch::channel(vec![]);
+
+    let recent_replays = Arc::new(RwLock::new(RecentReplays::load()));
+
+    let mut ui = Ui::new(
+        action_tx.clone(),
+        config.clone(),
+        recent_replays.clone(),
+        shortcuts_status.clone(),
+        bound_shortcuts_rx,
+    )
+    .await;
 
     let connection = Connection::session().await?;
-    let service_name = "ovh.kabus.TrayPlay";
     let proxy = zbus::fdo::DBusProxy::new(&connection).await?;
     let exists = proxy
-        .name_has_owner(BusName::try_from(service_name)?)
+        .name_has_owner(BusName::try_from(dbus::SERVICE_NAME)?)
         .await?;
 
     if exists {
@@ -88,123 +124,116 @@ async fn main() -> Result<(), Box<dyn Error>> {
         std::process::exit(1);
     }
 
-    let kwin_script_manager = KWinScriptManager::new().await?;
-    kwin_script_manager.load().await;
-
     // Let xdg portal know what desktop file are we
-    register_host_app(AppID::from_str("ovh.kabus.TrayPlay").unwrap()).await?;
-
-    let tray = TrayIcon::new(action_tx.clone(), &config).await;
-    // let tray = TrayIconClean::new(action_tx.clone(), &config);
-    let tray_handle = tray.spawn().await.unwrap();
-    shortcuts::setup_global_shortcuts(action_tx.clone());
+    register_host_app(AppID::from_str(dbus::SERVICE_NAME).unwrap()).await?;
+
+    let tray_handle = spawn_tray(
+        action_tx.clone(),
+        &config,
+        &recent_replays,
+        &shortcuts_status,
+    )
+    .await
+    .unwrap();
+    let (shortcuts_tx, shortcuts_rx) = watch::channel(config.read().await.shortcuts.clone());
+    let (configure_shortcuts_tx, configure_rx) = mpsc::channel(1);
+    shortcuts::setup_global_shortcuts(
+        ShortcutChannels {
+            shortcut_tx: action_tx.clone(),
+            shortcuts_rx,
+            bound_shortcuts_tx,
+            configure_rx,
+        },
+        shortcuts_status,
+    );
+
+    let window_history = Arc::new(RwLock::new(WindowHistory::default()));
+    let (service_connection, window_tx) =
+        active_window::setup_active_window_manager(window_history.clone(), config.clone()).await?;
+    service_connection
+        .object_server()
+        .at(
+            dbus::OBJECT_PATH,
+            MarkerService {
+                action_event_tx: action_tx.clone(),
+            },
+        )
+        .await?;
 
-    let app_name = Arc::new(RwLock::new("unknown".to_string()));
-    active_window::setup_active_window_manager(app_name.clone()).await?;
+    // Recording works without a source, replays just aren't named after the active app
+    let (window_source_kind, kwin_script_path) = {
+        let config = config.read().await;
+        (config.active_window_source, config.kwin_script_path.clone())
+    };
+    let mut window_source =
+        window_source::select(window_source_kind, kwin_script_path, &service_connection).await;
+    match &mut window_source {
+        Some(source) => {
+            info!("Following the active window with {}", source.name());
+            if let Err(err) = source.start(window_tx).await {
+                error!("{}", err);
+            }
+        }
+        None => info!("Not following the active window, replays will be saved as unknown"),
+    }
 
-    let mut gpu_screen_recorder = GpuScreenRecorder::new(config.clone(), app_name.clone()).await?;
-    if config.read().await.recording_enabled {
-        handle_gsr_start_result(gpu_screen_recorder.start().await);
+    let remux_locks = RemuxLocks::default();
+    let mut gpu_screen_recorder = GpuScreenRecorder::new(
+        config.clone(),
+        window_history.clone(),
+        action_tx.clone(),
+        remux_locks.clone(),
+    )
+    .await?;
+    if config.read().await.recording_enabled
+        && let Err(err) = gpu_screen_recorder.start().await
+    {
+        error!("{}", describe_start_error(err));
     }
 
     let conn = Connection::session().await?;
-    let osd_service = OsdServiceProxy::new(&conn).await?;
-
-    loop {
-        if let Some(action) = action_rx.recv().await {
-            match action {
-                ActionEvent::SaveReplay => {
-                    info!("Saving replay from {}", app_name.read().await);
-                    match gpu_screen_recorder.save_replay().await {
-                        Ok(_) => {
-                            osd_service
-                                .show_text(
-                                    "media-record",
-                                    &format!("Replay from \"{}\" saved!", app_name.read().await),
-                                )
-                                .await?;
-                        }
-                        Err(err) => match err {
-                            gsr::Error::RecorderNotRunning => {
-                                error!("Replay recording is either turned off or has crashed.")
-                            }
-                            err => {
-                                error!("Failed to save replay: {}", err);
-                            }
-                        },
-                    }
-                }
-                ActionEvent::Quit => {
-                    kwin_script_manager.unload().await;
-                    gpu_screen_recorder.stop().await?;
-                    std::process::exit(0);
-                }
-                ActionEvent::ChangeReplayPath => {
-                    let mut config = config.write().await;
-                    match ask_path(true, &config.replay_directory).await {
-                        Ok(directory) => {
-                            if let Some(directory) = directory {
-                                config.replay_directory = directory;
-                                config.save().await;
-                            }
-                        }
-                        Err(err) => {
-                            error!("Error when asking for replay directory: {}", err);
-                        }
-                    };
-                }
-                ActionEvent::ConfigSaved => {
-                    if gpu_screen_recorder.is_running() {
-                        gpu_screen_recorder.stop().await?;
-                        handle_gsr_start_result(gpu_screen_recorder.start().await);
-                    }
-                }
-                ActionEvent::ToggleReplay => {
-                    if gpu_screen_recorder.is_running() {
-                        gpu_screen_recorder.stop().await?;
-                        osd_service
-                            .show_text("media-playback-stopped", "Replay recording stopped")
-                            .await?;
-                        let mut config = config.write().await;
-                        config.recording_enabled = false;
-                        config.save().await;
-                    } else {
-                        gpu_screen_recorder.start().await?;
-                        osd_service
-                            .show_text("media-playback-playing", "Replay recording started")
-                            .await?;
-                        let mut config = config.write().await;
-                        config.recording_enabled = true;
-                        config.save().await;
-                    }
-                    tray_handle.update(|_| {}).await;
-                }
-                ActionEvent::ShowWindow(id) => {
-                    ui.show_window(&id);
-                }
-                ActionEvent::ShowInfo(title, text) => {
-                    let _ = ui.show_info(&title, &text).await;
-                }
-                ActionEvent::ShowError(title, text) => {
-                    let _ = ui.show_error(&title, &text).await;
-                }
-                other => {
-                    warn!("Unhandled action event: {:?}", other)
-                }
-            }
+    let (feedback_backend, feedback_events) = {
+        let config = config.read().await;
+        (config.feedback_backend, config.feedback_events)
+    };
+    let feedback = Feedback::new(
+        &conn,
+        feedback_backend,
+        feedback_events,
+        ui.toast(),
+        action_tx.clone(),
+    )
+    .await?;
+
+    let (janitor_tx, janitor_rx) = mpsc::channel(1);
+    retention::spawn_janitor(
+        config.clone(),
+        recent_replays.clone(),
+        remux_locks.clone(),
+        janitor_rx,
+    );
+
+    let mut app = App {
+        config,
+        ui,
+        recent_replays,
+        tray_handle,
+        gpu_screen_recorder,
+        window_history,
+        feedback,
+        window_source,
+        shortcuts_tx,
+        configure_shortcuts_tx,
+        transcode_tx: spawn_transcode_queue(action_tx.clone(), remux_locks.clone()),
+        janitor_tx,
+        remux_locks,
+    };
+
+    while let Some(action) = action_rx.recv().await {
+        if let Err(err) = app.handle_action(action).await {
+            error!("{}", err);
         }
     }
-}
 
-fn handle_gsr_start_result(result: Result<(), gsr::Error>) {
-    match result {
-        Ok(gsr) => gsr,
-        Err(err) => match err {
-            gsr::Error::IoError(err) => match err.kind() {
-                std::io::ErrorKind::NotFound => error!("gpu-screen-recorder is not installed!"),
-                err => error!("Error while starting gpu-screen-recorder: {}", err),
-            },
-            err => error!("Error while starting gpu-screen-recorder: {}", err),
-        },
-    }
+    Ok(())
 }
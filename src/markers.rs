@@ -0,0 +1,142 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use tokio::sync::mpsc::Sender;
+use zbus::interface;
+
+use crate::ActionEvent;
+
+/// Moments marked by the user while the recorder is running.
+#[derive(Debug, Default)]
+pub struct Markers {
+    /// Oldest moment that can still be in the replay buffer
+    buffer_start: Option<Instant>,
+    moments: Vec<Instant>,
+    /// Chapters of replays that were requested but not written to disk yet, oldest first
+    pending: VecDeque<Chapters>,
+}
+
+/// Marked moments relative to the start of a replay.
+#[derive(Debug, Default)]
+pub struct Chapters {
+    pub replay_length: Duration,
+    pub starts: Vec<Duration>,
+}
+
+impl Markers {
+    pub fn recorder_started(&mut self) {
+        self.buffer_start = Some(Instant::now());
+        self.moments.clear();
+    }
+
+    pub fn recorder_stopped(&mut self) {
+        self.buffer_start = None;
+        self.moments.clear();
+    }
+
+    /// Replay recording was turned off, so replays still pending won't show up anymore.
+    /// Restarts only call [`Self::recorder_stopped`].
+    pub fn session_ended(&mut self) {
+        self.recorder_stopped();
+        self.pending.clear();
+    }
+
+    /// Returns `false` when there is no buffer to mark.
+    pub fn mark(&mut self) -> bool {
+        if self.buffer_start.is_none() {
+            return false;
+        }
+
+        self.moments.push(Instant::now());
+        true
+    }
+
+    /// Remembers chapters for a replay covering `duration` seconds until now.
+    pub fn replay_requested(&mut self, duration: Duration, clear_buffer: bool) {
+        let Some(buffer_start) = self.buffer_start else {
+            return;
+        };
+
+        let now = Instant::now();
+        let window_start = now
+            .checked_sub(duration)
+            .map_or(buffer_start, |start| start.max(buffer_start));
+
+        self.pending.push_back(Chapters {
+            replay_length: now.duration_since(window_start),
+            starts: self
+                .moments
+                .iter()
+                .filter(|moment| **moment >= window_start)
+                .map(|moment| moment.duration_since(window_start))
+                .collect(),
+        });
+
+        if clear_buffer {
+            self.buffer_start = Some(now);
+            self.moments.clear();
+        } else {
+            self.moments.retain(|moment| *moment >= window_start);
+        }
+    }
+
+    pub fn take_pending(&mut self) -> Chapters {
+        self.pending.pop_front().unwrap_or_default()
+    }
+}
+
+/// Lets other programs (e.g. a stream deck) mark moments over D-Bus.
+pub struct MarkerService {
+    pub action_event_tx: Sender<ActionEvent>,
+}
+
+#[interface(name = "ovh.kabus.TrayPlay.Markers")]
+impl MarkerService {
+    async fn mark_moment(&self) {
+        let _ = self.action_event_tx.send(ActionEvent::MarkMoment).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn marked_replay() -> Markers {
+        let mut markers = Markers::default();
+        markers.recorder_started();
+        assert!(markers.mark());
+        markers.replay_requested(Duration::from_secs(60), false);
+        markers
+    }
+
+    #[test]
+    fn restart_keeps_pending_chapters() {
+        let mut markers = marked_replay();
+        markers.recorder_stopped();
+        markers.recorder_started();
+
+        assert_eq!(markers.take_pending().starts.len(), 1);
+    }
+
+    #[test]
+    fn session_end_drops_pending_chapters() {
+        let mut markers = marked_replay();
+        markers.session_ended();
+        markers.recorder_started();
+
+        assert!(markers.take_pending().starts.is_empty());
+    }
+
+    #[test]
+    fn marking_needs_a_running_recorder() {
+        let mut markers = Markers::default();
+        assert!(!markers.mark());
+
+        markers.recorder_started();
+        assert!(markers.mark());
+        markers.recorder_stopped();
+        assert!(!markers.mark());
+    }
+}
@@ -0,0 +1,98 @@
+use std::{
+    error::Error,
+    path::{Path, PathBuf},
+};
+
+use serde::Serialize;
+use time::OffsetDateTime;
+use tokio::fs;
+use toml::value::{Date, Datetime, Offset, Time};
+
+use crate::{
+    active_window::{ActiveWindow, WindowGeometry},
+    config::{Codec, Config, Container, Quality},
+};
+
+/// Context of a saved replay, written next to it as `<replay>.toml` so clips can be
+/// indexed without probing the video.
+#[derive(Serialize, Debug)]
+pub struct ReplayMetadata {
+    pub app_name: String,
+    pub desktop_file: String,
+    pub resource_class: String,
+    pub window_title: String,
+    pub output: String,
+    pub steam_app_id: Option<u32>,
+    pub is_game: bool,
+    pub pid: i32,
+    pub exe: Option<PathBuf>,
+    pub codec: Codec,
+    pub container: Container,
+    pub framerate: u32,
+    pub quality: Quality,
+    pub audio_tracks: Vec<String>,
+    pub replay_duration_secs: i64,
+    pub saved_at: Datetime,
+    pub window_geometry: WindowGeometry,
+}
+
+impl ReplayMetadata {
+    pub fn new(config: &Config, active_window: &ActiveWindow) -> Self {
+        Self {
+            app_name: active_window.app_name.clone(),
+            desktop_file: active_window.desktop_file.clone(),
+            resource_class: active_window.resource_class.clone(),
+            window_title: active_window.title.clone(),
+            output: active_window.output.clone(),
+            steam_app_id: active_window.steam_app_id,
+            is_game: active_window.is_game(),
+            pid: active_window.pid,
+            exe: active_window.exe.clone(),
+            codec: config.codec,
+            container: config.container,
+            framerate: config.framerate,
+            quality: config.quality,
+            audio_tracks: config.audio_tracks.clone(),
+            replay_duration_secs: config.replay_duration_secs,
+            saved_at: now_datetime(),
+            window_geometry: active_window.geometry,
+        }
+    }
+
+    pub fn sidecar_path(replay_path: &Path) -> PathBuf {
+        let mut path = replay_path.as_os_str().to_os_string();
+        path.push(".toml");
+        PathBuf::from(path)
+    }
+
+    pub async fn save(&self, replay_path: &Path) -> Result<(), Box<dyn Error>> {
+        fs::write(Self::sidecar_path(replay_path), toml::to_string(self)?).await?;
+
+        Ok(())
+    }
+}
+
+fn now_datetime() -> Datetime {
+    let now = OffsetDateTime::now_local().unwrap_or_else(|_| OffsetDateTime::now_utc());
+
+    Datetime {
+        date: Some(Date {
+            year: now.year() as u16,
+            month: now.month() as u8,
+            day: now.day(),
+        }),
+        time: Some(Time {
+            hour: now.hour(),
+            minute: now.minute(),
+            second: now.second(),
+            nanosecond: 0,
+        }),
+        offset: Some(if now.offset().is_utc() {
+            Offset::Z
+        } else {
+            Offset::Custom {
+                minutes: now.offset().whole_minutes(),
+            }
+        }),
+    }
+}
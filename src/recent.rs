@@ -0,0 +1,129 @@
+use std::path::{Path, PathBuf};
+
+use log::error;
+use serde::{Deserialize, Serialize};
+use time::{OffsetDateTime, UtcOffset};
+
+use crate::utils;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RecentReplay {
+    pub path: PathBuf,
+    pub app_name: String,
+    pub saved_at: i64,
+}
+
+impl RecentReplay {
+    pub fn new(path: PathBuf, app_name: String) -> Self {
+        Self {
+            path,
+            app_name,
+            saved_at: OffsetDateTime::now_utc().unix_timestamp(),
+        }
+    }
+
+    pub fn file_name(&self) -> String {
+        self.path
+            .file_name()
+            .map(|name| name.display().to_string())
+            .unwrap_or_else(|| self.path.display().to_string())
+    }
+
+    pub fn saved_at_display(&self) -> String {
+        let Ok(saved_at) = OffsetDateTime::from_unix_timestamp(self.saved_at) else {
+            return "unknown time".to_string();
+        };
+        let saved_at =
+            saved_at.to_offset(UtcOffset::current_local_offset().unwrap_or(UtcOffset::UTC));
+
+        format!(
+            "{}-{:0>2}-{:0>2} {:0>2}:{:0>2}",
+            saved_at.year(),
+            saved_at.month() as u8,
+            saved_at.day(),
+            saved_at.hour(),
+            saved_at.minute()
+        )
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct RecentReplays {
+    #[serde(default)]
+    replays: Vec<RecentReplay>,
+    /// Replays the retention policy must never remove
+    #[serde(default)]
+    kept: Vec<PathBuf>,
+}
+
+impl RecentReplays {
+    fn path() -> PathBuf {
+        let mut path = utils::data_dir();
+        path.push("trayplay");
+        path.push("recent_replays.toml");
+        path
+    }
+
+    pub fn load() -> Self {
+        match std::fs::read_to_string(Self::path()) {
+            Ok(recent) => {
+                let mut recent: Self = toml::from_str(&recent).unwrap_or_else(|err| {
+                    error!("Cannot parse recent replays list: {}", err);
+                    Self::default()
+                });
+                // Forget replays that were moved or deleted while we weren't running
+                recent.replays.retain(|replay| replay.path.exists());
+                recent.kept.retain(|path| path.exists());
+
+                recent
+            }
+            Err(_) => Self::default(),
+        }
+    }
+
+    pub fn save(&self) {
+        let path = Self::path();
+        if let Some(parent) = path.parent() {
+            let _ = std::fs::create_dir_all(parent);
+        }
+
+        if let Err(err) = std::fs::write(path, toml::to_string(&self).unwrap()) {
+            error!("Failed to write recent replays list: {}", err);
+        }
+    }
+
+    /// Newest replays first.
+    pub fn replays(&self) -> &[RecentReplay] {
+        &self.replays
+    }
+
+    pub fn push(&mut self, replay: RecentReplay, limit: usize) {
+        self.replays.retain(|r| r.path != replay.path);
+        self.replays.insert(0, replay);
+        self.replays.truncate(limit);
+        self.save();
+    }
+
+    pub fn remove(&mut self, path: &Path) {
+        self.replays.retain(|r| r.path != path);
+        self.kept.retain(|kept| kept != path);
+        self.save();
+    }
+
+    pub fn kept(&self) -> &[PathBuf] {
+        &self.kept
+    }
+
+    pub fn is_kept(&self, path: &Path) -> bool {
+        self.kept.iter().any(|kept| kept == path)
+    }
+
+    pub fn toggle_kept(&mut self, path: &Path) {
+        if self.is_kept(path) {
+            self.kept.retain(|kept| kept != path);
+        } else {
+            self.kept.push(path.to_path_buf());
+        }
+        self.save();
+    }
+}
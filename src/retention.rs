@@ -0,0 +1,377 @@
+use std::{
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use log::{info, warn};
+use time::OffsetDateTime;
+use tokio::sync::{RwLock, mpsc::Receiver};
+
+use crate::{
+    config::{Config, RetentionAction, RetentionPolicy},
+    container::RemuxLocks,
+    metadata::ReplayMetadata,
+    recent::RecentReplays,
+    utils::{self, trash_file},
+};
+
+const JANITOR_INTERVAL: Duration = Duration::from_secs(60 * 60);
+const REPLAY_EXTENSIONS: [&str; 4] = ["mkv", "mp4", "flv", "webm"];
+/// Files written next to a replay that go away together with it
+const SIDECAR_SUFFIXES: [&str; 2] = [".toml", ".chapters.txt"];
+
+#[derive(Debug)]
+struct StoredReplay {
+    path: PathBuf,
+    size: u64,
+    modified: SystemTime,
+}
+
+/// Replays in `replay_directory` that `policy` wants gone, oldest first. Kept replays
+/// are never included, but still count towards the total size.
+///
+/// `replay_directory` is `~/Videos` by default, so only videos TrayPlay saved itself are
+/// considered: those listed in `saved` and those with a metadata sidecar.
+pub fn plan(
+    policy: &RetentionPolicy,
+    replay_directory: &Path,
+    kept: &[PathBuf],
+    saved: &HashSet<PathBuf>,
+) -> Vec<PathBuf> {
+    if !policy.is_enabled() {
+        return vec![];
+    }
+
+    let mut replays = vec![];
+    collect_replays(replay_directory, &mut replays);
+    replays.retain(|replay| {
+        saved.contains(&replay.path) || ReplayMetadata::sidecar_path(&replay.path).exists()
+    });
+    // Archived replays may be moved into a subdirectory, they are out of our hands then
+    if let RetentionAction::MoveTo(directory) = &policy.action {
+        replays.retain(|replay| !replay.path.starts_with(directory));
+    }
+    // Newest first
+    replays.sort_by_key(|replay| Reverse(replay.modified));
+
+    let now = SystemTime::now();
+    let mut total_size = replays.iter().map(|replay| replay.size).sum::<u64>();
+    let mut count_per_app = HashMap::<&Path, usize>::new();
+    let mut removed = vec![false; replays.len()];
+
+    for (i, replay) in replays.iter().enumerate() {
+        if kept.contains(&replay.path) {
+            continue;
+        }
+
+        // Replays are grouped by app through their directory, like the default file name pattern does
+        let count = count_per_app
+            .entry(replay.path.parent().unwrap_or(replay_directory))
+            .or_default();
+        *count += 1;
+
+        let too_old = policy.max_age_days.is_some_and(|days| {
+            now.duration_since(replay.modified).unwrap_or_default()
+                > Duration::from_secs(days as u64 * 24 * 60 * 60)
+        });
+        let too_many = policy.max_count_per_app.is_some_and(|max| *count > max);
+
+        if too_old || too_many {
+            removed[i] = true;
+            total_size -= replay.size;
+        }
+    }
+
+    if let Some(max_total_size_gb) = policy.max_total_size_gb {
+        let max_total_size = max_total_size_gb * 1024 * 1024 * 1024;
+        for (i, replay) in replays.iter().enumerate().rev() {
+            if total_size <= max_total_size {
+                break;
+            }
+            if !removed[i] && !kept.contains(&replay.path) {
+                removed[i] = true;
+                total_size -= replay.size;
+            }
+        }
+    }
+
+    replays
+        .into_iter()
+        .zip(removed)
+        .rev()
+        .filter(|(_, removed)| *removed)
+        .map(|(replay, _)| replay.path)
+        .collect()
+}
+
+fn collect_replays(directory: &Path, replays: &mut Vec<StoredReplay>) {
+    let Ok(entries) = std::fs::read_dir(directory) else {
+        return;
+    };
+
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let path = entry.path();
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+
+        if metadata.is_dir() {
+            collect_replays(&path, replays);
+        } else if path
+            .extension()
+            .is_some_and(|extension| REPLAY_EXTENSIONS.iter().any(|e| extension == *e))
+        {
+            replays.push(StoredReplay {
+                size: metadata.len(),
+                modified: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+                path,
+            });
+        }
+    }
+}
+
+fn saved_replays_path() -> PathBuf {
+    let mut path = utils::data_dir();
+    path.push("trayplay");
+    path.push("saved_replays.txt");
+    path
+}
+
+/// Keeps [`record_saved`] from appending while the janitor rewrites the ledger.
+static LEDGER_LOCK: Mutex<()> = Mutex::new(());
+
+/// Remembers that TrayPlay wrote `path`, which makes it subject to the retention policy.
+pub fn record_saved(path: &Path) {
+    let _ledger = LEDGER_LOCK.lock().unwrap();
+    let ledger_path = saved_replays_path();
+    if let Some(parent) = ledger_path.parent() {
+        let _ = std::fs::create_dir_all(parent);
+    }
+
+    let result = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&ledger_path)
+        .and_then(|mut file| writeln!(file, "{}", path.display()));
+
+    if let Err(err) = result {
+        info!("Cannot write to {}: {}", ledger_path.display(), err);
+    }
+}
+
+/// Replays TrayPlay saved that still exist, from the ledger written by [`record_saved`]
+/// and the recent replays list.
+pub fn saved_replays(recent_replays: &RecentReplays) -> HashSet<PathBuf> {
+    read_ledger()
+        .into_iter()
+        .chain(
+            recent_replays
+                .replays()
+                .iter()
+                .map(|replay| replay.path.clone()),
+        )
+        .filter(|path| path.exists())
+        .collect()
+}
+
+fn read_ledger() -> Vec<PathBuf> {
+    std::fs::read_to_string(saved_replays_path())
+        .unwrap_or_default()
+        .lines()
+        .map(PathBuf::from)
+        .collect()
+}
+
+/// Drops replays that don't exist anymore from the ledger, so it doesn't grow forever.
+fn prune_ledger() {
+    let _ledger = LEDGER_LOCK.lock().unwrap();
+    let ledger_path = saved_replays_path();
+    if !ledger_path.exists() {
+        return;
+    }
+
+    let mut seen = HashSet::new();
+    let contents = read_ledger()
+        .into_iter()
+        .filter(|path| path.exists() && seen.insert(path.clone()))
+        .map(|path| format!("{}\n", path.display()))
+        .collect::<String>();
+
+    if let Err(err) = std::fs::write(&ledger_path, contents) {
+        info!("Cannot write to {}: {}", ledger_path.display(), err);
+    }
+}
+
+/// Periodically removes replays according to `retention` in the config. Also runs whenever
+/// something is sent to `trigger_rx`, e.g. after a replay is saved.
+pub fn spawn_janitor(
+    config: Arc<RwLock<Config>>,
+    recent_replays: Arc<RwLock<RecentReplays>>,
+    remux_locks: RemuxLocks,
+    mut trigger_rx: Receiver<()>,
+) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(JANITOR_INTERVAL);
+        // Replays that couldn't be removed, e.g. without a trash. Retrying them on every run
+        // would only fail again, so they are left alone until the policy changes.
+        let mut failed = HashSet::<PathBuf>::new();
+        let mut last_policy = None;
+
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {}
+                Some(()) = trigger_rx.recv() => {}
+                else => return,
+            }
+
+            let config_values = config.read().await;
+            let policy = config_values.retention.clone();
+            let replay_directory = config_values.replay_directory.clone();
+            drop(config_values);
+
+            if last_policy.as_ref() != Some(&policy) {
+                failed.clear();
+                last_policy = Some(policy.clone());
+            }
+
+            let recent_replays_values = recent_replays.read().await;
+            let kept = recent_replays_values.kept().to_vec();
+            let saved = saved_replays(&recent_replays_values);
+            drop(recent_replays_values);
+            let removed = plan(&policy, &replay_directory, &kept, &saved);
+
+            let mut errors = vec![];
+            for path in removed {
+                if failed.contains(&path) {
+                    continue;
+                }
+
+                remux_locks.wait(&path).await;
+                let result = match &policy.action {
+                    RetentionAction::Trash => trash_replay(&path).await,
+                    RetentionAction::Delete => delete_replay(&path),
+                    RetentionAction::MoveTo(directory) => move_replay(&path, directory),
+                };
+
+                match result {
+                    Ok(()) => {
+                        recent_replays.write().await.remove(&path);
+                        log_removal(&path, &policy.action);
+                    }
+                    Err(err) => {
+                        info!("Retention policy cannot remove {}: {}", path.display(), err);
+                        errors.push(err);
+                        failed.insert(path);
+                    }
+                }
+            }
+
+            // Warnings show a dialog, so only one per run
+            if let Some(err) = errors.first() {
+                warn!(
+                    "Retention policy cannot remove {} replays, not trying again until it changes: {}",
+                    errors.len(),
+                    err
+                );
+            }
+
+            prune_ledger();
+        }
+    });
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_os_string();
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+/// Moves a replay to the trash together with the files written next to it.
+pub async fn trash_replay(path: &Path) -> Result<(), String> {
+    trash_file(path).await.map_err(|err| err.to_string())?;
+    for suffix in SIDECAR_SUFFIXES {
+        let sidecar = with_suffix(path, suffix);
+        if sidecar.exists() {
+            trash_file(&sidecar).await.map_err(|err| err.to_string())?;
+        }
+    }
+
+    Ok(())
+}
+
+fn delete_replay(path: &Path) -> Result<(), String> {
+    std::fs::remove_file(path).map_err(|err| err.to_string())?;
+    for suffix in SIDECAR_SUFFIXES {
+        let _ = std::fs::remove_file(with_suffix(path, suffix));
+    }
+
+    Ok(())
+}
+
+fn move_replay(path: &Path, directory: &Path) -> Result<(), String> {
+    std::fs::create_dir_all(directory).map_err(|err| err.to_string())?;
+
+    for (from, suffix) in std::iter::once((path.to_path_buf(), ""))
+        .chain(SIDECAR_SUFFIXES.map(|suffix| (with_suffix(path, suffix), suffix)))
+    {
+        if !from.exists() {
+            continue;
+        }
+
+        let to = with_suffix(
+            &directory.join(path.file_name().unwrap_or_default()),
+            suffix,
+        );
+        // rename doesn't work across filesystems, which archive directories often are on
+        if std::fs::rename(&from, &to).is_err() {
+            std::fs::copy(&from, &to).map_err(|err| err.to_string())?;
+            std::fs::remove_file(&from).map_err(|err| err.to_string())?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Appends to `retention.log` in the data directory, so it's possible to find out what
+/// happened to a missing replay.
+fn log_removal(path: &Path, action: &RetentionAction) {
+    let action = match action {
+        RetentionAction::Trash => "moved to trash".to_string(),
+        RetentionAction::Delete => "deleted".to_string(),
+        RetentionAction::MoveTo(directory) => format!("moved to {}", directory.display()),
+    };
+    info!("Retention policy: {} {}", path.display(), action);
+
+    let mut log_path = utils::data_dir();
+    log_path.push("trayplay");
+    let _ = std::fs::create_dir_all(&log_path);
+    log_path.push("retention.log");
+
+    let now = OffsetDateTime::now_local().unwrap_or_else(|_| OffsetDateTime::now_utc());
+    let result = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&log_path)
+        .and_then(|mut file| {
+            writeln!(
+                file,
+                "{}-{:0>2}-{:0>2} {:0>2}:{:0>2}:{:0>2} {} {}",
+                now.year(),
+                now.month() as u8,
+                now.day(),
+                now.hour(),
+                now.minute(),
+                now.second(),
+                path.display(),
+                action
+            )
+        });
+
+    if let Err(err) = result {
+        info!("Cannot write to {}: {}", log_path.display(), err);
+    }
+}
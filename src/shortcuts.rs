@@ -1,73 +1,175 @@
+use std::{
+    fmt::Display,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
 use futures_util::StreamExt;
 
 use ashpd::desktop::{
     Session,
     global_shortcuts::{GlobalShortcuts, NewShortcut},
 };
-use lazy_static::lazy_static;
-use tokio::sync::mpsc::Sender;
+use log::{debug, info, warn};
+use tokio::sync::{
+    RwLock,
+    mpsc::{self, Sender},
+    watch,
+};
 
-use crate::ActionEvent;
+use crate::{
+    ActionEvent,
+    config::{Shortcut, ShortcutAction},
+    kglobalaccel::KGlobalAccelShortcuts,
+};
 
-lazy_static! {
-    static ref SHORTCUTS: Vec<(&'static str, &'static str, &'static str)> = vec![
-        // id, description, trigger
-        ("save-replay", "Save replay", "ALT+F10"),
-        ("toggle-replay", "Toggle replay", "ALT+SHIFT+F10"),
-        ("quit", "Quit program", "ALT+SHIFT+F11")
-    ];
+impl From<&ShortcutAction> for ActionEvent {
+    fn from(value: &ShortcutAction) -> Self {
+        match value {
+            ShortcutAction::SaveReplay => ActionEvent::SaveReplay,
+            ShortcutAction::ToggleReplay => ActionEvent::ToggleReplay,
+            ShortcutAction::OpenSettings => ActionEvent::ShowWindow("window".to_string()),
+            ShortcutAction::Quit => ActionEvent::Quit,
+            ShortcutAction::MarkMoment => ActionEvent::MarkMoment,
+            ShortcutAction::SetFramerate(framerate) => ActionEvent::SetFramerate(*framerate),
+            ShortcutAction::SetQuality(quality) => ActionEvent::SetQuality(*quality),
+            ShortcutAction::SetReplayDuration(secs) => ActionEvent::SetReplayDuration(*secs),
+            ShortcutAction::SetVideoSource(screen) => ActionEvent::SetVideoSource(screen.clone()),
+        }
+    }
+}
+
+/// Shortcut as currently bound by the desktop, shown in the settings window.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BoundShortcut {
+    pub id: String,
+    pub description: String,
+    /// Human readable trigger, empty if none is assigned
+    pub trigger: String,
+}
+
+impl From<&ashpd::desktop::global_shortcuts::Shortcut> for BoundShortcut {
+    fn from(value: &ashpd::desktop::global_shortcuts::Shortcut) -> Self {
+        Self {
+            id: value.id().to_string(),
+            description: value.description().to_string(),
+            trigger: value.trigger_description().to_string(),
+        }
+    }
 }
 
 pub struct GlobalShortcutManager<'a> {
     global_shortcuts_wrapper: GlobalShortcuts<'a>,
     global_shortcuts_session: Session<'a, GlobalShortcuts<'a>>,
     shortcut_tx: Sender<ActionEvent>,
+    bound_shortcuts_tx: watch::Sender<Vec<BoundShortcut>>,
+    shortcuts: Vec<Shortcut>,
 }
 
 impl<'a> GlobalShortcutManager<'a> {
-    pub async fn new(shortcut_tx: Sender<ActionEvent>) -> Result<Self, GlobalShortcutManagerError> {
+    pub async fn new(
+        shortcut_tx: Sender<ActionEvent>,
+        bound_shortcuts_tx: watch::Sender<Vec<BoundShortcut>>,
+        shortcuts: Vec<Shortcut>,
+    ) -> Result<Self, GlobalShortcutManagerError> {
         let wrapper = GlobalShortcuts::new().await?;
         Ok(Self {
             global_shortcuts_session: wrapper.create_session().await?,
             global_shortcuts_wrapper: wrapper,
             shortcut_tx,
+            bound_shortcuts_tx,
+            shortcuts,
         })
     }
 
+    async fn publish_bound_shortcuts(&self) -> Result<(), GlobalShortcutManagerError> {
+        let request = self
+            .global_shortcuts_wrapper
+            .list_shortcuts(&self.global_shortcuts_session)
+            .await?;
+
+        self.bound_shortcuts_tx.send_replace(
+            request
+                .response()?
+                .shortcuts()
+                .iter()
+                .map(BoundShortcut::from)
+                .collect(),
+        );
+
+        Ok(())
+    }
+
+    /// Lets the user change triggers in the desktop's own dialog. Portals older than
+    /// version 2 don't have that, binding again is the closest thing they offer.
+    pub async fn configure(&self) -> Result<(), GlobalShortcutManagerError> {
+        if let Err(err) = self
+            .global_shortcuts_wrapper
+            .configure_shortcuts(&self.global_shortcuts_session, None, None)
+            .await
+        {
+            info!(
+                "ConfigureShortcuts failed, binding shortcuts again: {}",
+                err
+            );
+            self.bind_all().await?;
+        }
+
+        Ok(())
+    }
+
     pub async fn register_all(&self) -> Result<(), GlobalShortcutManagerError> {
         let request = self
             .global_shortcuts_wrapper
             .list_shortcuts(&self.global_shortcuts_session)
             .await?;
 
-        let shortcut_ids = request
+        let bound_shortcuts = request
             .response()?
             .shortcuts()
             .iter()
-            .map(|shortcut| shortcut.id().to_string())
-            .collect::<Vec<String>>();
+            .map(|shortcut| {
+                (
+                    shortcut.id().to_string(),
+                    shortcut.description().to_string(),
+                )
+            })
+            .collect::<Vec<(String, String)>>();
+
+        // Binding shows a dialog on some desktops, so only do it when something changed
+        let up_to_date = self.shortcuts.iter().all(|shortcut| {
+            bound_shortcuts.contains(&(shortcut.id.clone(), shortcut.description.clone()))
+        });
+
+        if !up_to_date {
+            self.bind_all().await?;
+        }
+
+        self.publish_bound_shortcuts().await
+    }
 
-        let shortcuts: Vec<NewShortcut> = SHORTCUTS
+    async fn bind_all(&self) -> Result<(), GlobalShortcutManagerError> {
+        let shortcuts: Vec<NewShortcut> = self
+            .shortcuts
             .iter()
-            .filter(|s| !shortcut_ids.contains(&s.0.to_string()))
-            .map(|s| NewShortcut::new(s.0, s.1).preferred_trigger(s.2))
+            .map(|s| {
+                NewShortcut::new(&s.id, &s.description).preferred_trigger(s.trigger.as_deref())
+            })
             .collect();
 
-        if !shortcuts.is_empty() {
-            let request = self
-                .global_shortcuts_wrapper
-                .bind_shortcuts(&self.global_shortcuts_session, &shortcuts, None)
-                .await;
-
-            // Ignore missing field error for now - looks like a bug in ashpd or in KDE 6.4 beta
-            if let Err(error) = &request {
-                if let ashpd::Error::Zbus(zbus::Error::Variant(zbus::zvariant::Error::Message(
-                    message,
-                ))) = error
-                {
-                    if message != "missing field `shortcuts`" {
-                        request?;
-                    }
+        let request = self
+            .global_shortcuts_wrapper
+            .bind_shortcuts(&self.global_shortcuts_session, &shortcuts, None)
+            .await;
+
+        // Ignore missing field error for now - looks like a bug in ashpd or in KDE 6.4 beta
+        if let Err(error) = &request {
+            if let ashpd::Error::Zbus(zbus::Error::Variant(zbus::zvariant::Error::Message(
+                message,
+            ))) = error
+            {
+                if message != "missing field `shortcuts`" {
+                    request?;
                 }
             }
         }
@@ -75,19 +177,125 @@ impl<'a> GlobalShortcutManager<'a> {
         Ok(())
     }
 
-    pub async fn run_handler(&self) -> Result<(), GlobalShortcutManagerError> {
+    /// Binds the new shortcut list in a fresh session, as portals only allow binding once
+    /// per session.
+    pub async fn rebind(
+        &mut self,
+        shortcuts: Vec<Shortcut>,
+    ) -> Result<(), GlobalShortcutManagerError> {
+        info!("Shortcut list changed, binding shortcuts again");
+
+        self.shortcuts = shortcuts;
+        self.global_shortcuts_session.close().await?;
+        self.global_shortcuts_session = self.global_shortcuts_wrapper.create_session().await?;
+
+        self.bind_all().await?;
+        self.publish_bound_shortcuts().await
+    }
+
+    fn action_for(&self, shortcut_id: &str) -> ActionEvent {
+        self.shortcuts
+            .iter()
+            .find(|shortcut| shortcut.id == shortcut_id)
+            .map(|shortcut| ActionEvent::from(&shortcut.action))
+            .unwrap_or(ActionEvent::Unknown)
+    }
+
+    /// Keeps handling shortcuts when the portal goes away for a moment, e.g. when it's
+    /// restarted: its signal streams end then, so subscribe again with a fresh session.
+    /// Gives up after [`RESUBSCRIBE_ATTEMPTS`] failed tries, so another backend can take over.
+    pub async fn run_handler(
+        &mut self,
+        mut shortcuts_rx: watch::Receiver<Vec<Shortcut>>,
+        configure_rx: &mut mpsc::Receiver<()>,
+        status: &RwLock<ShortcutsStatus>,
+    ) -> Result<(), GlobalShortcutManagerError> {
+        let mut retry_delay = RESUBSCRIBE_MIN_DELAY;
+
+        loop {
+            let started = Instant::now();
+            match self.handle_events(&mut shortcuts_rx, configure_rx).await {
+                Ok(()) => info!("GlobalShortcuts portal stopped sending events"),
+                Err(GlobalShortcutManagerError::AshpdError(err)) => {
+                    info!("Cannot listen to GlobalShortcuts portal: {}", err)
+                }
+                Err(err) => return Err(err),
+            }
+            set_status(status, &self.shortcut_tx, ShortcutsStatus::Reconnecting).await;
+
+            // Only back off when the portal keeps failing right away
+            if started.elapsed() > RESUBSCRIBE_MAX_DELAY {
+                retry_delay = RESUBSCRIBE_MIN_DELAY;
+            }
+
+            let mut attempt = 1;
+            loop {
+                info!(
+                    "Subscribing to GlobalShortcuts portal again in {}s",
+                    retry_delay.as_secs()
+                );
+                tokio::time::sleep(retry_delay).await;
+                retry_delay = (retry_delay * 2).min(RESUBSCRIBE_MAX_DELAY);
+
+                match self.reconnect().await {
+                    Ok(()) => break,
+                    Err(err) if attempt >= RESUBSCRIBE_ATTEMPTS => return Err(err),
+                    Err(err) => info!("Cannot reconnect to GlobalShortcuts portal: {:?}", err),
+                }
+                attempt += 1;
+            }
+            set_status(status, &self.shortcut_tx, ShortcutsStatus::Portal).await;
+        }
+    }
+
+    /// The old session went away with the portal, so shortcuts have to be bound again.
+    async fn reconnect(&mut self) -> Result<(), GlobalShortcutManagerError> {
+        // Most likely gone already, but don't leave it behind if it isn't
+        if let Err(err) = self.global_shortcuts_session.close().await {
+            debug!("Cannot close old GlobalShortcuts session: {}", err);
+        }
+        self.global_shortcuts_session = self.global_shortcuts_wrapper.create_session().await?;
+        self.register_all().await
+    }
+
+    /// Returns once the portal stops sending activations.
+    async fn handle_events(
+        &mut self,
+        shortcuts_rx: &mut watch::Receiver<Vec<Shortcut>>,
+        configure_rx: &mut mpsc::Receiver<()>,
+    ) -> Result<(), GlobalShortcutManagerError> {
+        let mut activated = self.global_shortcuts_wrapper.receive_activated().await?;
+        let mut shortcuts_changed = self
+            .global_shortcuts_wrapper
+            .receive_shortcuts_changed()
+            .await?;
+
         loop {
-            if let Ok(mut activated) = self.global_shortcuts_wrapper.receive_activated().await {
-                while let Some(activation) = activated.next().await {
+            tokio::select! {
+                activation = activated.next() => {
+                    let Some(activation) = activation else {
+                        return Ok(());
+                    };
                     self.shortcut_tx
-                        .send(match activation.shortcut_id() {
-                            "save-replay" => ActionEvent::SaveReplay,
-                            "quit" => ActionEvent::Quit,
-                            "toggle-replay" => ActionEvent::ToggleReplay,
-                            _ => ActionEvent::Unknown,
-                        })
+                        .send(self.action_for(activation.shortcut_id()))
                         .await?;
                 }
+                Some(changed) = shortcuts_changed.next() => {
+                    self.bound_shortcuts_tx.send_replace(
+                        changed.shortcuts().iter().map(BoundShortcut::from).collect(),
+                    );
+                }
+                Some(()) = configure_rx.recv() => {
+                    if let Err(err) = self.configure().await {
+                        warn!("Cannot open shortcut configuration: {:?}", err);
+                    }
+                }
+                Ok(()) = shortcuts_rx.changed() => {
+                    let shortcuts = shortcuts_rx.borrow_and_update().clone();
+                    if let Err(err) = self.rebind(shortcuts).await {
+                        warn!("Cannot bind changed shortcuts: {:?}", err);
+                    }
+                }
             }
         }
     }
@@ -112,20 +320,159 @@ impl From<tokio::sync::mpsc::error::SendError<ActionEvent>> for GlobalShortcutMa
     }
 }
 
-pub fn setup_global_shortcuts(shortcut_tx: Sender<ActionEvent>) {
+/// How global shortcuts are currently delivered, shown in the tray and settings.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ShortcutsStatus {
+    #[default]
+    Connecting,
+    Portal,
+    /// The portal went away and TrayPlay is trying to get it back
+    Reconnecting,
+    KGlobalAccel,
+    Unavailable,
+}
+
+impl Display for ShortcutsStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                ShortcutsStatus::Connecting => "Connecting to the shortcuts service...",
+                ShortcutsStatus::Portal => "Global shortcuts are active",
+                ShortcutsStatus::Reconnecting =>
+                    "Lost connection to GlobalShortcuts portal, reconnecting...",
+                ShortcutsStatus::KGlobalAccel =>
+                    "GlobalShortcuts portal is missing, using KGlobalAccel instead",
+                ShortcutsStatus::Unavailable =>
+                    "Global shortcuts are unavailable, neither GlobalShortcuts portal nor KGlobalAccel was found",
+            }
+        )
+    }
+}
+
+const PORTAL_RETRY_INTERVAL: Duration = Duration::from_secs(30);
+const RESUBSCRIBE_MIN_DELAY: Duration = Duration::from_secs(1);
+const RESUBSCRIBE_MAX_DELAY: Duration = Duration::from_secs(60);
+/// Failed reconnects to the portal before falling back to KGlobalAccel
+const RESUBSCRIBE_ATTEMPTS: u32 = 5;
+
+async fn is_portal_available() -> bool {
+    match GlobalShortcuts::new().await {
+        Ok(wrapper) => wrapper.get_property::<u32>("version").await.is_ok(),
+        Err(_) => false,
+    }
+}
+
+async fn wait_for_portal() {
+    loop {
+        tokio::time::sleep(PORTAL_RETRY_INTERVAL).await;
+        if is_portal_available().await {
+            return;
+        }
+    }
+}
+
+async fn set_status(
+    status: &RwLock<ShortcutsStatus>,
+    shortcut_tx: &Sender<ActionEvent>,
+    new_status: ShortcutsStatus,
+) {
+    let mut status = status.write().await;
+    if *status != new_status {
+        *status = new_status;
+        let _ = shortcut_tx.send(ActionEvent::ShortcutsStatusChanged).await;
+    }
+}
+
+async fn run_portal(
+    channels: &mut ShortcutChannels,
+    status: &RwLock<ShortcutsStatus>,
+) -> Result<(), GlobalShortcutManagerError> {
+    let shortcut_tx = channels.shortcut_tx.clone();
+    let shortcuts = channels.shortcuts_rx.borrow().clone();
+    let mut global_shortcuts_manager = GlobalShortcutManager::new(
+        shortcut_tx.clone(),
+        channels.bound_shortcuts_tx.clone(),
+        shortcuts,
+    )
+    .await?;
+    global_shortcuts_manager.register_all().await?;
+
+    set_status(status, &shortcut_tx, ShortcutsStatus::Portal).await;
+    global_shortcuts_manager
+        .run_handler(
+            channels.shortcuts_rx.clone(),
+            &mut channels.configure_rx,
+            status,
+        )
+        .await
+}
+
+async fn run_kglobalaccel(
+    channels: &mut ShortcutChannels,
+    status: &RwLock<ShortcutsStatus>,
+) -> zbus::Result<()> {
+    let shortcut_tx = channels.shortcut_tx.clone();
+    let shortcuts = channels.shortcuts_rx.borrow().clone();
+    let mut fallback = KGlobalAccelShortcuts::new(
+        shortcut_tx.clone(),
+        channels.bound_shortcuts_tx.clone(),
+        shortcuts,
+    )
+    .await?;
+    fallback.register_all().await?;
+
+    set_status(status, &shortcut_tx, ShortcutsStatus::KGlobalAccel).await;
+    let result = tokio::select! {
+        result = fallback.run_handler(channels.shortcuts_rx.clone(), &mut channels.configure_rx) => result,
+        _ = wait_for_portal() => {
+            info!("GlobalShortcuts portal appeared, switching over from KGlobalAccel");
+            Ok(())
+        }
+    };
+    fallback.unregister_all().await;
+
+    result
+}
+
+/// Channels connecting the shortcut backends with the rest of the app.
+pub struct ShortcutChannels {
+    /// Activated shortcuts are sent here as [`ActionEvent`]s
+    pub shortcut_tx: Sender<ActionEvent>,
+    /// Shortcut list from the config, backends rebind when it changes
+    pub shortcuts_rx: watch::Receiver<Vec<Shortcut>>,
+    /// Triggers as reported by the desktop, for the settings window
+    pub bound_shortcuts_tx: watch::Sender<Vec<BoundShortcut>>,
+    /// Requests to open the desktop's shortcut configuration
+    pub configure_rx: mpsc::Receiver<()>,
+}
+
+/// Keeps global shortcuts working for the whole lifetime of the app. Prefers the
+/// GlobalShortcuts portal, falls back to KGlobalAccel and retries the portal
+/// periodically in case it shows up later (e.g. when started before the portal at login).
+pub fn setup_global_shortcuts(
+    mut channels: ShortcutChannels,
+    status: Arc<RwLock<ShortcutsStatus>>,
+) {
     tokio::spawn(async move {
-        let global_shortcuts_manager = GlobalShortcutManager::new(shortcut_tx)
-            .await
-            .expect("Cannot setup global shortcuts");
+        let mut warned = false;
 
-        global_shortcuts_manager
-            .register_all()
-            .await
-            .expect("Cannot register global shortcuts");
+        loop {
+            if let Err(err) = run_portal(&mut channels, &status).await {
+                info!("GlobalShortcuts portal is not usable: {:?}", err);
+            }
 
-        global_shortcuts_manager
-            .run_handler()
-            .await
-            .expect("Cannot run shortcut handler");
+            if let Err(err) = run_kglobalaccel(&mut channels, &status).await {
+                info!("KGlobalAccel is not usable: {}", err);
+
+                set_status(&status, &channels.shortcut_tx, ShortcutsStatus::Unavailable).await;
+                if !warned {
+                    warn!("{}", ShortcutsStatus::Unavailable);
+                    warned = true;
+                }
+                tokio::time::sleep(PORTAL_RETRY_INTERVAL).await;
+            }
+        }
     });
 }
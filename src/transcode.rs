@@ -0,0 +1,262 @@
+use std::{
+    error::Error,
+    path::{Path, PathBuf},
+    process::Stdio,
+};
+
+use log::{error, info};
+use tokio::{
+    io::{AsyncBufReadExt, BufReader},
+    process::Command,
+    sync::mpsc::{self, Receiver, Sender},
+};
+
+use crate::{
+    ActionEvent,
+    config::{Container, TranscodeProfile},
+    container::RemuxLocks,
+    utils::check_free_space,
+};
+
+/// Audio bitrate used when re-encoding, in kbit/s.
+const AUDIO_BITRATE: u64 = 128;
+
+#[derive(Debug, Clone)]
+pub struct TranscodeJob {
+    pub input: PathBuf,
+    pub app_name: String,
+    pub profile: TranscodeProfile,
+}
+
+impl TranscodeJob {
+    /// Whether the video gets re-encoded to H.264 and AAC instead of copied.
+    fn reencodes(&self) -> bool {
+        self.profile.max_height.is_some() || self.profile.max_size_mb.is_some()
+    }
+
+    /// WebM and FLV can't hold what a re-encode produces, so those clips are written as MP4.
+    fn container(&self) -> Container {
+        match self.profile.container {
+            Container::WEBM | Container::FLV if self.reencodes() => Container::MP4,
+            container => container,
+        }
+    }
+
+    fn output(&self) -> PathBuf {
+        let stem = self
+            .input
+            .file_stem()
+            .map(|stem| stem.display().to_string())
+            .unwrap_or_default();
+
+        self.input.with_file_name(format!(
+            "{}_{}.{}",
+            stem,
+            self.profile.suffix,
+            self.container().to_string()
+        ))
+    }
+}
+
+/// Starts a worker converting replays one at a time, so several saves in a row don't
+/// make ffmpeg processes fight over the CPU.
+pub fn spawn_transcode_queue(
+    action_event_tx: Sender<ActionEvent>,
+    remux_locks: RemuxLocks,
+) -> Sender<TranscodeJob> {
+    let (job_tx, job_rx) = mpsc::channel(32);
+    tokio::spawn(run_queue(job_rx, action_event_tx, remux_locks));
+    job_tx
+}
+
+async fn run_queue(
+    mut job_rx: Receiver<TranscodeJob>,
+    action_event_tx: Sender<ActionEvent>,
+    remux_locks: RemuxLocks,
+) {
+    while let Some(job) = job_rx.recv().await {
+        // Converting a replay that's still being tagged would lose its chapters
+        remux_locks.wait(&job.input).await;
+
+        let output = job.output();
+        info!(
+            "Converting {} with profile {}",
+            job.input.display(),
+            job.profile.name
+        );
+
+        // Errors aren't Send, so keep only the message while awaiting
+        let result = transcode(&job, &output, &job_rx, &action_event_tx)
+            .await
+            .map_err(|err| err.to_string());
+
+        match result {
+            Ok(()) => {
+                let _ = action_event_tx
+                    .send(ActionEvent::TranscodeFinished(
+                        output,
+                        job.app_name,
+                        job.profile.name,
+                    ))
+                    .await;
+            }
+            Err(err) => {
+                let _ = tokio::fs::remove_file(&output).await;
+                error!(
+                    "Converting {} with profile {} failed: {}",
+                    job.input.display(),
+                    job.profile.name,
+                    err
+                );
+            }
+        }
+
+        if job_rx.is_empty() {
+            let _ = action_event_tx
+                .send(ActionEvent::TranscodeProgress(None))
+                .await;
+        }
+    }
+}
+
+async fn transcode(
+    job: &TranscodeJob,
+    output: &Path,
+    job_rx: &Receiver<TranscodeJob>,
+    action_event_tx: &Sender<ActionEvent>,
+) -> Result<(), Box<dyn Error>> {
+    let profile = &job.profile;
+    let duration_secs = probe_duration(&job.input).await;
+
+    // Conversions are rarely bigger than the replay, unless they have a size limit
+    let needed = match profile.max_size_mb {
+        Some(max_size_mb) => max_size_mb as u64 * 1024 * 1024,
+        None => tokio::fs::metadata(&job.input).await?.len(),
+    };
+    check_free_space(output.parent().unwrap_or(Path::new("/")), needed)?;
+
+    let mut command = Command::new("ffmpeg");
+    command
+        .args([
+            "-y",
+            "-nostats",
+            "-loglevel",
+            "error",
+            "-progress",
+            "pipe:1",
+        ])
+        .arg("-i")
+        .arg(&job.input);
+
+    if !job.reencodes() {
+        command.args(["-map", "0", "-codec", "copy"]);
+    } else {
+        // Shared clips are played by apps that only know about the first audio track
+        command.args([
+            "-map", "0:v:0", "-map", "0:a:0?", "-c:v", "libx264", "-preset", "veryfast", "-c:a",
+            "aac", "-b:a",
+        ]);
+        command.arg(format!("{}k", AUDIO_BITRATE));
+
+        if let Some(max_height) = profile.max_height {
+            command
+                .arg("-vf")
+                .arg(format!("scale=-2:'min({},ih)'", max_height));
+        }
+
+        match profile.max_size_mb {
+            Some(max_size_mb) => {
+                let duration_secs = duration_secs.ok_or("cannot read replay duration")?;
+                // Leave some room for container overhead
+                let total_bitrate =
+                    (max_size_mb as f64 * 8.0 * 1024.0 * 0.95 / duration_secs.max(1.0)) as u64;
+                let video_bitrate = total_bitrate.saturating_sub(AUDIO_BITRATE).max(100);
+                command
+                    .arg("-b:v")
+                    .arg(format!("{}k", video_bitrate))
+                    .arg("-maxrate")
+                    .arg(format!("{}k", video_bitrate))
+                    .arg("-bufsize")
+                    .arg(format!("{}k", video_bitrate * 2));
+            }
+            None => {
+                command.args(["-crf", "23"]);
+            }
+        }
+    }
+
+    if job.container() == Container::MP4 {
+        command.args(["-movflags", "+faststart"]);
+    }
+
+    let mut child = command
+        .arg(output)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()?;
+
+    let mut lines = BufReader::new(child.stdout.take().unwrap()).lines();
+    let mut last_percent = None;
+    while let Some(line) = lines.next_line().await? {
+        let Some(out_time_us) = line
+            .strip_prefix("out_time_us=")
+            .and_then(|time| time.parse::<f64>().ok())
+        else {
+            continue;
+        };
+
+        let percent = duration_secs
+            .map(|duration| (out_time_us / 1_000_000.0 / duration * 100.0).clamp(0.0, 100.0) as u8);
+        if percent == last_percent {
+            continue;
+        }
+        last_percent = percent;
+
+        let mut status = format!(
+            "Converting {} to {}",
+            job.input
+                .file_name()
+                .map(|name| name.display().to_string())
+                .unwrap_or_default(),
+            profile.name
+        );
+        if let Some(percent) = percent {
+            status += &format!(": {}%", percent);
+        }
+        if !job_rx.is_empty() {
+            status += &format!(" ({} more queued)", job_rx.len());
+        }
+
+        // Don't hold up the conversion when the action channel is busy, the next update
+        // replaces a skipped one anyway
+        let _ = action_event_tx.try_send(ActionEvent::TranscodeProgress(Some(status)));
+    }
+
+    let output = child.wait_with_output().await?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().into());
+    }
+
+    Ok(())
+}
+
+async fn probe_duration(path: &Path) -> Option<f64> {
+    let output = Command::new("ffprobe")
+        .args([
+            "-v",
+            "error",
+            "-show_entries",
+            "format=duration",
+            "-of",
+            "default=noprint_wrappers=1:nokey=1",
+        ])
+        .arg(path)
+        .stdin(Stdio::null())
+        .output()
+        .await
+        .ok()?;
+
+    String::from_utf8_lossy(&output.stdout).trim().parse().ok()
+}
@@ -1,28 +1,328 @@
-use log::error;
-use std::{iter::once, process::Command, sync::Arc};
+use log::{error, info};
+use std::{
+    iter::once,
+    path::PathBuf,
+    process::Command,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
 
 use ksni::{
-    MenuItem,
+    Handle, MenuItem, Orientation, TrayMethods,
     menu::{CheckmarkItem, RadioGroup, RadioItem, StandardItem, SubMenu},
 };
 use tokio::sync::{RwLock, mpsc::Sender};
 
-use crate::{ActionEvent, config::Config};
+use crate::{
+    ActionEvent,
+    config::{Config, Quality, TrayAction, TrayScrollAction},
+    recent::RecentReplays,
+    shortcuts::ShortcutsStatus,
+    utils::get_command_output,
+};
 
-pub struct TrayIcon {
+/// `MENU_ON_ACTIVATE` can't change at runtime, so it is decided once by
+/// `tray_primary_action` when the tray is spawned.
+pub struct TrayIcon<const MENU_ON_ACTIVATE: bool> {
     _enabled: bool,
     tray_event_tx: Sender<ActionEvent>,
     config: Arc<RwLock<Config>>,
+    recent_replays: Arc<RwLock<RecentReplays>>,
+    shortcuts_status: Arc<RwLock<ShortcutsStatus>>,
+    video_sources: Vec<String>,
+    /// Shown in the tooltip while something runs in the background, e.g. a conversion
+    status: Option<String>,
+    /// Bumped on every scroll step, so only the last one of a gesture gets saved
+    scroll_generation: Arc<AtomicU64>,
 }
 
-impl TrayIcon {
-    pub async fn new(tray_event_tx: Sender<ActionEvent>, config: &Arc<RwLock<Config>>) -> Self {
+/// How long scrolling has to stop before the new replay duration is saved. Saving restarts
+/// the recorder, which throws away the replay buffer.
+const SCROLL_SAVE_DELAY: Duration = Duration::from_millis(1500);
+
+impl<const MENU_ON_ACTIVATE: bool> TrayIcon<MENU_ON_ACTIVATE> {
+    pub async fn new(
+        tray_event_tx: Sender<ActionEvent>,
+        config: &Arc<RwLock<Config>>,
+        recent_replays: &Arc<RwLock<RecentReplays>>,
+        shortcuts_status: &Arc<RwLock<ShortcutsStatus>>,
+    ) -> Self {
         Self {
             tray_event_tx,
             _enabled: true,
             config: config.clone(),
+            recent_replays: recent_replays.clone(),
+            shortcuts_status: shortcuts_status.clone(),
+            status: None,
+            scroll_generation: Arc::new(AtomicU64::new(0)),
+            video_sources: get_command_output("gpu-screen-recorder", &["--list-monitors"])
+                .unwrap_or_default()
+                .split('\n')
+                .filter(|source| !source.is_empty())
+                .map(|source| source.to_string())
+                .collect(),
+        }
+    }
+
+    fn run_action(&self, action: TrayAction) {
+        let event = match action {
+            TrayAction::None => return,
+            TrayAction::Menu => {
+                // Not a dialog, this would show up on every middle click
+                info!("Opening the menu is only supported as the primary tray action");
+                return;
+            }
+            TrayAction::SaveReplay => ActionEvent::SaveReplay,
+            TrayAction::ToggleReplay => ActionEvent::ToggleReplay,
+            TrayAction::OpenSettings => ActionEvent::ShowWindow("window".to_string()),
+        };
+
+        futures::executor::block_on(async {
+            self.tray_event_tx.send(event).await.unwrap();
+        });
+    }
+
+    /// Hidden when shortcuts work through the portal, as that's the expected setup.
+    fn shortcuts_status_item(&self) -> MenuItem<Self> {
+        let status = futures::executor::block_on(async { *self.shortcuts_status.read().await });
+
+        StandardItem {
+            label: status.to_string(),
+            icon_name: match status {
+                ShortcutsStatus::Unavailable | ShortcutsStatus::Reconnecting => "dialog-warning",
+                _ => "dialog-information",
+            }
+            .into(),
+            enabled: false,
+            visible: status != ShortcutsStatus::Portal,
+            ..Default::default()
+        }
+        .into()
+    }
+
+    fn recent_replays_menu(&self) -> MenuItem<Self> {
+        let recent_replays =
+            futures::executor::block_on(async { self.recent_replays.read().await });
+        let transcode_profiles = futures::executor::block_on(async {
+            self.config.read().await.transcode_profiles.clone()
+        });
+
+        let submenu = if recent_replays.replays().is_empty() {
+            vec![
+                StandardItem {
+                    label: "No replays saved yet".into(),
+                    enabled: false,
+                    ..Default::default()
+                }
+                .into(),
+            ]
+        } else {
+            recent_replays
+                .replays()
+                .iter()
+                .map(|replay| {
+                    let replay_action =
+                        |label: &str, icon: &str, event: fn(PathBuf) -> ActionEvent| {
+                            let tx_clone = self.tray_event_tx.clone();
+                            let path = replay.path.clone();
+                            StandardItem {
+                                label: label.into(),
+                                icon_name: icon.into(),
+                                activate: Box::new(move |_| {
+                                    futures::executor::block_on(async {
+                                        tx_clone.send(event(path.clone())).await.unwrap();
+                                    });
+                                }),
+                                ..Default::default()
+                            }
+                            .into()
+                        };
+
+                    SubMenu {
+                        label: escape_label(&format!(
+                            "{} ({}, {})",
+                            replay.file_name(),
+                            replay.app_name,
+                            replay.saved_at_display()
+                        )),
+                        icon_name: "video-x-generic".into(),
+                        submenu: vec![
+                            replay_action("Open", "document-open", ActionEvent::OpenReplay),
+                            replay_action(
+                                "Show in folder",
+                                "folder-open",
+                                ActionEvent::ShowReplayInFolder,
+                            ),
+                            replay_action("Copy path", "edit-copy", ActionEvent::CopyReplayPath),
+                            CheckmarkItem {
+                                label: "Keep forever".into(),
+                                checked: recent_replays.is_kept(&replay.path),
+                                activate: Box::new({
+                                    let tx_clone = self.tray_event_tx.clone();
+                                    let path = replay.path.clone();
+                                    move |_| {
+                                        futures::executor::block_on(async {
+                                            tx_clone
+                                                .send(ActionEvent::ToggleKeepReplay(path.clone()))
+                                                .await
+                                                .unwrap();
+                                        });
+                                    }
+                                }),
+                                ..Default::default()
+                            }
+                            .into(),
+                            SubMenu {
+                                label: "Convert to".into(),
+                                icon_name: "document-export".into(),
+                                visible: !transcode_profiles.is_empty(),
+                                submenu: transcode_profiles
+                                    .iter()
+                                    .map(|profile| {
+                                        let tx_clone = self.tray_event_tx.clone();
+                                        let path = replay.path.clone();
+                                        let profile_name = profile.name.clone();
+                                        StandardItem {
+                                            label: escape_label(&profile.name),
+                                            activate: Box::new(move |_| {
+                                                futures::executor::block_on(async {
+                                                    tx_clone
+                                                        .send(ActionEvent::Transcode(
+                                                            path.clone(),
+                                                            profile_name.clone(),
+                                                        ))
+                                                        .await
+                                                        .unwrap();
+                                                });
+                                            }),
+                                            ..Default::default()
+                                        }
+                                        .into()
+                                    })
+                                    .collect(),
+                                ..Default::default()
+                            }
+                            .into(),
+                            MenuItem::Separator,
+                            replay_action("Move to trash", "user-trash", ActionEvent::TrashReplay),
+                        ],
+                        ..Default::default()
+                    }
+                    .into()
+                })
+                .collect()
+        };
+
+        SubMenu {
+            label: "Recent replays".into(),
+            icon_name: "document-open-recent".into(),
+            submenu,
+            ..Default::default()
+        }
+        .into()
+    }
+}
+
+pub enum TrayHandle {
+    MenuOnActivate(Handle<TrayIcon<true>>),
+    ActionOnActivate(Handle<TrayIcon<false>>),
+}
+
+impl TrayHandle {
+    pub async fn update(&self) {
+        match self {
+            TrayHandle::MenuOnActivate(handle) => {
+                handle.update(|_| {}).await;
+            }
+            TrayHandle::ActionOnActivate(handle) => {
+                handle.update(|_| {}).await;
+            }
         }
     }
+
+    pub async fn set_status(&self, status: Option<String>) {
+        match self {
+            TrayHandle::MenuOnActivate(handle) => {
+                handle.update(|tray| tray.status = status).await;
+            }
+            TrayHandle::ActionOnActivate(handle) => {
+                handle.update(|tray| tray.status = status).await;
+            }
+        }
+    }
+}
+
+pub async fn spawn_tray(
+    tray_event_tx: Sender<ActionEvent>,
+    config: &Arc<RwLock<Config>>,
+    recent_replays: &Arc<RwLock<RecentReplays>>,
+    shortcuts_status: &Arc<RwLock<ShortcutsStatus>>,
+) -> Result<TrayHandle, ksni::Error> {
+    let primary_action = config.read().await.tray_primary_action;
+
+    Ok(if primary_action == TrayAction::Menu {
+        TrayHandle::MenuOnActivate(
+            TrayIcon::<true>::new(tray_event_tx, config, recent_replays, shortcuts_status)
+                .await
+                .spawn()
+                .await?,
+        )
+    } else {
+        TrayHandle::ActionOnActivate(
+            TrayIcon::<false>::new(tray_event_tx, config, recent_replays, shortcuts_status)
+                .await
+                .spawn()
+                .await?,
+        )
+    })
+}
+
+fn video_source_option(source: &str) -> TrayMultipleOption<String> {
+    match source.split_once('|') {
+        Some((name, resolution)) => TrayMultipleOption(
+            escape_label(&format!("{} ({})", name, resolution)),
+            name.to_string(),
+        ),
+        None => TrayMultipleOption(escape_label(source), source.to_string()),
+    }
+}
+
+/// Entry for a source set in the config that isn't listed otherwise, e.g. `focused` or an
+/// unplugged monitor, so the menu still shows what's recorded.
+fn other_video_source_option(
+    video_sources: &[String],
+    screen: &str,
+) -> Option<TrayMultipleOption<String>> {
+    let listed = ["screen", "portal"].contains(&screen)
+        || video_sources
+            .iter()
+            .any(|source| video_source_option(source).1 == screen);
+    if listed {
+        return None;
+    }
+
+    let label = match screen {
+        "focused" => "Focused window".to_string(),
+        "region" => "Region".to_string(),
+        screen => format!("{} (not connected)", screen),
+    };
+    Some(TrayMultipleOption(escape_label(&label), screen.to_string()))
+}
+
+fn format_duration(secs: i64) -> String {
+    if secs % 60 == 0 {
+        format!("{} min", secs / 60)
+    } else {
+        format!("{} s", secs)
+    }
+}
+
+/// Escapes underscores so they aren't treated as access key markers by the menu.
+fn escape_label(label: &str) -> String {
+    label.replace('_', "__")
 }
 
 struct TrayMultipleOption<T>(String, T);
@@ -132,7 +432,7 @@ where
 
 macro_rules! tray_config_item_custom {
     ($label:expr, $icon:expr, $action:expr) => {
-        TrayConfigItem::Custom::<TrayIcon, u8> {
+        TrayConfigItem::Custom::<Self, u8> {
             label: $label.into(),
             icon: $icon.into(),
             action: Box::new(|item| {
@@ -144,9 +444,52 @@ macro_rules! tray_config_item_custom {
     };
 }
 
+macro_rules! tray_config_item_multiple {
+    ($label:expr, $icon:expr, $options:expr, $current:expr, $show_custom:expr, $action:expr) => {{
+        let options: Vec<TrayMultipleOption<_>> = $options;
+        let values = options
+            .iter()
+            .map(|option| option.1.clone())
+            .collect::<Vec<_>>();
+        // Selects "Custom..." when current value isn't one of the presets
+        let initial_state = values
+            .iter()
+            .position(|value| *value == $current)
+            .unwrap_or(values.len());
+
+        TrayConfigItem::Multiple::<Self, _> {
+            label: $label.into(),
+            icon: $icon.into(),
+            options,
+            initial_state,
+            show_custom: $show_custom,
+            action: Box::new(move |item, index| {
+                futures::executor::block_on(async {
+                    match values.get(index) {
+                        Some(value) => {
+                            $action(
+                                item.config.clone(),
+                                item.tray_event_tx.clone(),
+                                value.clone(),
+                            )
+                            .await;
+                        }
+                        None => {
+                            item.tray_event_tx
+                                .send(ActionEvent::ShowWindow("window".to_string()))
+                                .await
+                                .unwrap();
+                        }
+                    }
+                });
+            }),
+        }
+    }};
+}
+
 macro_rules! tray_config_item_toggle {
     ($label:expr, $icon:expr, $initial_state:expr, $action:expr) => {
-        TrayConfigItem::Toggle::<TrayIcon, u8> {
+        TrayConfigItem::Toggle::<Self, u8> {
             label: $label.into(),
             icon: $icon.into(),
             initial_state: $initial_state,
@@ -159,8 +502,57 @@ macro_rules! tray_config_item_toggle {
     };
 }
 
-impl ksni::Tray for TrayIcon {
-    const MENU_ON_ACTIVATE: bool = true;
+impl<const MENU_ON_ACTIVATE: bool> ksni::Tray for TrayIcon<MENU_ON_ACTIVATE> {
+    const MENU_ON_ACTIVATE: bool = MENU_ON_ACTIVATE;
+
+    fn activate(&mut self, _x: i32, _y: i32) {
+        let action =
+            futures::executor::block_on(async { self.config.read().await.tray_primary_action });
+        self.run_action(action);
+    }
+
+    fn secondary_activate(&mut self, _x: i32, _y: i32) {
+        let action = futures::executor::block_on(async {
+            self.config.read().await.tray_middle_click_action
+        });
+        self.run_action(action);
+    }
+
+    fn scroll(&mut self, delta: i32, orientation: Orientation) {
+        if orientation != Orientation::Vertical || delta == 0 {
+            return;
+        }
+
+        let action =
+            futures::executor::block_on(async { self.config.read().await.tray_scroll_action });
+
+        match action {
+            TrayScrollAction::None => {}
+            TrayScrollAction::ReplayDuration => {
+                // Applied right away so further steps add up, but only saved once scrolling stops
+                let duration = futures::executor::block_on(async {
+                    let mut config = self.config.write().await;
+                    // Scrolling up (negative delta) makes the buffer longer
+                    let step = config.tray_scroll_step_secs.max(1);
+                    let duration = config.replay_duration_secs - step * delta.signum() as i64;
+                    config.replay_duration_secs = duration.max(step);
+                    config.replay_duration_secs
+                });
+
+                let generation = self.scroll_generation.fetch_add(1, Ordering::Relaxed) + 1;
+                let scroll_generation = self.scroll_generation.clone();
+                let tray_event_tx = self.tray_event_tx.clone();
+                tokio::spawn(async move {
+                    tokio::time::sleep(SCROLL_SAVE_DELAY).await;
+                    if scroll_generation.load(Ordering::Relaxed) == generation {
+                        let _ = tray_event_tx
+                            .send(ActionEvent::SetReplayDuration(duration))
+                            .await;
+                    }
+                });
+            }
+        }
+    }
 
     fn id(&self) -> String {
         env!("CARGO_PKG_NAME").into()
@@ -174,6 +566,14 @@ impl ksni::Tray for TrayIcon {
         "TrayPlay".into()
     }
 
+    fn tool_tip(&self) -> ksni::ToolTip {
+        ksni::ToolTip {
+            title: "TrayPlay".into(),
+            description: self.status.clone().unwrap_or_default(),
+            ..Default::default()
+        }
+    }
+
     fn menu(&self) -> Vec<ksni::MenuItem<Self>> {
         let tx_clone = self.tray_event_tx.clone();
         use ksni::menu::*;
@@ -207,6 +607,103 @@ impl ksni::Tray for TrayIcon {
                 ..Default::default()
             }
             .into(),
+            StandardItem {
+                label: "Mark moment".into(),
+                icon_name: "bookmark-new".into(),
+                enabled: config.recording_enabled,
+                activate: Box::new({
+                    let tx_clone = tx_clone.clone();
+                    move |_| {
+                        futures::executor::block_on(async {
+                            tx_clone.send(ActionEvent::MarkMoment).await.unwrap();
+                        });
+                    }
+                }),
+                ..Default::default()
+            }
+            .into(),
+            self.recent_replays_menu(),
+            MenuItem::Separator,
+            tray_config_item_multiple!(
+                "Framerate",
+                "video-display",
+                [30, 60, 120, 144]
+                    .into_iter()
+                    .map(|framerate| TrayMultipleOption(format!("{} FPS", framerate), framerate))
+                    .collect(),
+                config.framerate,
+                true,
+                async |config: Arc<RwLock<Config>>, _, framerate| {
+                    let mut config = config.write().await;
+                    config.framerate = framerate;
+                    config.save().await;
+                }
+            )
+            .into(),
+            tray_config_item_multiple!(
+                "Quality",
+                "games-config-options",
+                vec![
+                    TrayMultipleOption("Medium".to_string(), Quality::Medium),
+                    TrayMultipleOption("High".to_string(), Quality::High),
+                    TrayMultipleOption("Very high".to_string(), Quality::VeryHigh),
+                    TrayMultipleOption("Ultra".to_string(), Quality::Ultra),
+                ],
+                config.quality,
+                false,
+                async |config: Arc<RwLock<Config>>, _, quality| {
+                    let mut config = config.write().await;
+                    config.quality = quality;
+                    config.save().await;
+                }
+            )
+            .into(),
+            tray_config_item_multiple!(
+                "Replay duration",
+                "chronometer",
+                [30, 60, 120, 180, 300, 600]
+                    .into_iter()
+                    .map(|secs| TrayMultipleOption(format_duration(secs), secs))
+                    .collect(),
+                config.replay_duration_secs,
+                true,
+                async |config: Arc<RwLock<Config>>, _, replay_duration_secs| {
+                    let mut config = config.write().await;
+                    config.replay_duration_secs = replay_duration_secs;
+                    config.save().await;
+                }
+            )
+            .into(),
+            tray_config_item_multiple!(
+                "Video source",
+                "video-television",
+                once(TrayMultipleOption(
+                    "Default".to_string(),
+                    "screen".to_string()
+                ))
+                .chain(
+                    self.video_sources
+                        .iter()
+                        .map(|source| video_source_option(source))
+                )
+                .chain(once(TrayMultipleOption(
+                    "XDG Desktop Portal".to_string(),
+                    "portal".to_string()
+                )))
+                .chain(other_video_source_option(
+                    &self.video_sources,
+                    &config.screen
+                ))
+                .collect(),
+                config.screen,
+                false,
+                async |config: Arc<RwLock<Config>>, _, screen| {
+                    let mut config = config.write().await;
+                    config.screen = screen;
+                    config.save().await;
+                }
+            )
+            .into(),
             MenuItem::Separator,
             StandardItem {
                 label: "Settings".into(),
@@ -242,6 +739,7 @@ impl ksni::Tray for TrayIcon {
                 ..Default::default()
             }
             .into(),
+            self.shortcuts_status_item(),
             MenuItem::Separator,
             StandardItem {
                 label: "Quit".into(),
@@ -7,12 +7,14 @@ use qttypes::{QString, QUrl, QVariant};
 use tokio::sync::{
     RwLock,
     mpsc::{self, Receiver, Sender},
-    oneshot,
+    oneshot, watch,
 };
 
 use crate::{
     ActionEvent,
     config::Config,
+    recent::RecentReplays,
+    shortcuts::{BoundShortcut, ShortcutsStatus},
     ui::{
         messagebox::{MessageBoxHelper, MessageBoxResult},
         settings::Settings,
@@ -28,6 +30,7 @@ cpp! {{
     #include <QCoreApplication>
     #include <QtGui/QGuiApplication>
     #include <QtGui/QIcon>
+    #include <QtGui/QClipboard>
     #include <QQuickStyle>
     #include <QQmlContext>
     #include <QQmlApplicationEngine>
@@ -39,15 +42,25 @@ cpp! {{
 pub struct Ui {
     change_window_visibility: Arc<dyn Fn((QString, bool))>,
     show_message_box: Arc<dyn Fn((QString, QString, QString))>,
+    show_toast: Arc<dyn Fn((QString, QString))>,
+    copy_to_clipboard: Arc<dyn Fn(QString)>,
     message_box_result_rx: Receiver<MessageBoxResult>,
 }
 
 impl Ui {
-    pub async fn new(action_event_tx: Sender<ActionEvent>, config: Arc<RwLock<Config>>) -> Self {
+    pub async fn new(
+        action_event_tx: Sender<ActionEvent>,
+        config: Arc<RwLock<Config>>,
+        recent_replays: Arc<RwLock<RecentReplays>>,
+        shortcuts_status: Arc<RwLock<ShortcutsStatus>>,
+        bound_shortcuts_rx: watch::Receiver<Vec<BoundShortcut>>,
+    ) -> Self {
         let (message_box_result_tx, message_box_result_rx) = mpsc::channel(8);
 
         let (settings_cb_tx, settings_cb_rx) = oneshot::channel();
         let (message_box_cb_tx, message_box_cb_rx) = oneshot::channel();
+        let (toast_cb_tx, toast_cb_rx) = oneshot::channel();
+        let (clipboard_cb_tx, clipboard_cb_rx) = oneshot::channel();
         tokio::spawn(async move {
             let mut engine = QmlEngine::new();
 
@@ -108,7 +121,14 @@ impl Ui {
                 });
             }
 
-            let settings = Settings::new(config, action_event_tx).await;
+            let settings = Settings::new(
+                config,
+                recent_replays,
+                action_event_tx,
+                shortcuts_status,
+                bound_shortcuts_rx,
+            )
+            .await;
             qml_register_singleton_instance(cstr!("Settings"), 1, 0, cstr!("Settings"), settings);
 
             let message_box_helper = MessageBoxHelper::new(message_box_result_tx);
@@ -153,12 +173,34 @@ impl Ui {
                 })
             }));
 
+            let _ = toast_cb_tx.send(Arc::new({
+                let engine_ptr = engine.cpp_ptr();
+                queued_callback(move |params: (QString, QString)| {
+                    let icon = QVariant::from(params.0);
+                    let text = QVariant::from(params.1);
+
+                    cpp!(unsafe [engine_ptr as "QQmlEngine *", icon as "QVariant", text as "QVariant"] {
+                        QObject* root_object = ((QQmlApplicationEngine*)engine_ptr)->rootObjects().first();
+                        QObject* toast = root_object->findChild<QObject*>("toast");
+                        QMetaObject::invokeMethod(toast, "popup", Q_ARG(QVariant, icon), Q_ARG(QVariant, text));
+                    });
+                })
+            }));
+
+            let _ = clipboard_cb_tx.send(Arc::new(queued_callback(move |text: QString| {
+                cpp!(unsafe [text as "QString"] {
+                    QGuiApplication::clipboard()->setText(text);
+                });
+            })));
+
             engine.exec();
         });
 
         let obj = Self {
             change_window_visibility: settings_cb_rx.await.unwrap(),
             show_message_box: message_box_cb_rx.await.unwrap(),
+            show_toast: toast_cb_rx.await.unwrap(),
+            copy_to_clipboard: clipboard_cb_rx.await.unwrap(),
             message_box_result_rx,
         };
 
@@ -169,6 +211,14 @@ impl Ui {
         self.change_window_visibility.as_ref()((QString::from(id), true));
     }
 
+    pub fn toast(&self) -> Toast {
+        Toast(self.show_toast.clone())
+    }
+
+    pub fn copy_to_clipboard(&self, text: &str) {
+        self.copy_to_clipboard.as_ref()(QString::from(text));
+    }
+
     pub async fn show_info(&mut self, title: &str, text: &str) -> MessageBoxResult {
         self.show_message_box.as_ref()((
             QString::from("dialog-information"),
@@ -188,11 +238,24 @@ impl Ui {
     }
 }
 
+/// Small transient window shown by TrayPlay itself, works on any desktop.
+#[derive(Clone)]
+pub struct Toast(Arc<dyn Fn((QString, QString))>);
+
+impl Toast {
+    pub fn show(&self, icon: &str, text: &str) {
+        self.0.as_ref()((QString::from(icon), QString::from(text)));
+    }
+}
+
 qrc!(settings_ui, "ui" as "ui" {
     "settings.qml",
     "AudioPage.qml",
     "MainPage.qml",
+    "ShortcutsPage.qml",
+    "StoragePage.qml",
     "MessageBox.qml",
+    "Toast.qml",
     "components/ConfigLabel.qml",
     "lang/pl_PL.qm",
     "lang/de_DE.qm",
@@ -6,9 +6,16 @@ use qmetaobject::{
     QSingletonInit, QString, QStringList, QVariantList, prelude::QObject, qt_base_class, qt_method,
     qt_property, qt_signal,
 };
-use tokio::sync::{RwLock, mpsc::Sender};
+use tokio::sync::{RwLock, mpsc::Sender, watch};
 
-use crate::{ActionEvent, config::Config, utils::get_command_output};
+use crate::{
+    ActionEvent,
+    config::{Config, RetentionAction, RetentionPolicy},
+    recent::RecentReplays,
+    retention,
+    shortcuts::{BoundShortcut, ShortcutsStatus},
+    utils::get_command_output,
+};
 
 cpp! {{
     #include <QTranslator>
@@ -61,7 +68,10 @@ macro_rules! property_impl {
 #[derive(QObject, Default)]
 pub struct Settings {
     config: Arc<RwLock<Config>>,
+    recent_replays: Arc<RwLock<RecentReplays>>,
     action_event_tx: Option<Sender<ActionEvent>>,
+    shortcuts_status: Arc<RwLock<ShortcutsStatus>>,
+    bound_shortcuts_rx: Option<watch::Receiver<Vec<BoundShortcut>>>,
     base: qt_base_class!(trait QObject),
     framerate: qt_property!(u32; READ get_framerate WRITE set_framerate),
     duration: qt_property!(u32; READ get_duration WRITE set_duration),
@@ -78,13 +88,23 @@ pub struct Settings {
     video_source_choice: qt_property!(QString; READ get_video_source_choice WRITE set_video_source_choice),
     audio_tracks_inner: Vec<Vec<String>>,
     audio_tracks: qt_property!(QVariantList; READ get_audio_tracks NOTIFY change),
+    shortcuts: qt_property!(QVariantList; READ get_shortcuts NOTIFY shortcuts_changed),
+    retention_max_size_gb: qt_property!(u32; READ get_retention_max_size_gb WRITE set_retention_max_size_gb),
+    retention_max_age_days: qt_property!(u32; READ get_retention_max_age_days WRITE set_retention_max_age_days),
+    retention_max_count: qt_property!(u32; READ get_retention_max_count WRITE set_retention_max_count),
     apply_config: qt_method!(fn(&self)),
     remove_audio_source: qt_method!(fn(&mut self, track: usize, source: usize)),
     add_audio_source: qt_method!(fn(&mut self, track: usize, source: QString)),
     remove_audio_track: qt_method!(fn(&mut self, track: usize)),
     add_audio_track: qt_method!(fn(&mut self)),
     move_audio_track: qt_method!(fn(&mut self, track: usize, target_index: usize)),
+    shortcuts_status_text: qt_method!(fn(&self) -> QString),
+    refresh_shortcuts: qt_method!(fn(&mut self)),
+    configure_shortcuts: qt_method!(fn(&self)),
+    retention_preview: qt_method!(fn(&self) -> QStringList),
+    apply_retention: qt_method!(fn(&self)),
     change: qt_signal!(),
+    shortcuts_changed: qt_signal!(),
 }
 
 impl Settings {
@@ -101,6 +121,57 @@ impl Settings {
     property_impl!(audio_devices, QStringList, cloned);
     property_impl!(video_sources, QStringList, cloned);
     property_impl!(video_source_choice, QString, cloned);
+    property_impl!(retention_max_size_gb, u32);
+    property_impl!(retention_max_age_days, u32);
+    property_impl!(retention_max_count, u32);
+
+    /// Retention policy built from the properties, 0 means unlimited.
+    fn retention_policy(&self, action: RetentionAction) -> RetentionPolicy {
+        RetentionPolicy {
+            max_total_size_gb: (self.retention_max_size_gb > 0)
+                .then_some(self.retention_max_size_gb as u64),
+            max_age_days: (self.retention_max_age_days > 0).then_some(self.retention_max_age_days),
+            max_count_per_app: (self.retention_max_count > 0)
+                .then_some(self.retention_max_count as usize),
+            action,
+        }
+    }
+
+    /// Replays that would be removed with the current values, without touching anything.
+    fn retention_preview(&self) -> QStringList {
+        let config = futures::executor::block_on(async { self.config.read().await });
+        let policy = self.retention_policy(config.retention.action.clone());
+
+        let recent_replays =
+            futures::executor::block_on(async { self.recent_replays.read().await });
+
+        retention::plan(
+            &policy,
+            &config.replay_directory,
+            recent_replays.kept(),
+            &retention::saved_replays(&recent_replays),
+        )
+        .iter()
+        .map(|path| path.display().to_string())
+        .collect()
+    }
+
+    fn apply_retention(&self) {
+        futures::executor::block_on(async {
+            let mut config = self.config.write().await;
+            config.retention = self.retention_policy(config.retention.action.clone());
+            // Saving would restart the recorder and lose the replay buffer
+            config.write();
+            drop(config);
+
+            self.action_event_tx
+                .as_ref()
+                .unwrap()
+                .send(ActionEvent::ApplyRetention)
+                .await
+                .unwrap();
+        });
+    }
 
     fn get_audio_tracks(&self) -> QVariantList {
         self.audio_tracks_inner
@@ -140,6 +211,56 @@ impl Settings {
         self.change();
     }
 
+    /// Each shortcut is a `[description, trigger]` pair.
+    fn get_shortcuts(&self) -> QVariantList {
+        self.bound_shortcuts_rx
+            .as_ref()
+            .map(|rx| {
+                rx.borrow()
+                    .iter()
+                    .map(|shortcut| {
+                        QStringList::from(vec![
+                            shortcut.description.as_str(),
+                            shortcut.trigger.as_str(),
+                        ])
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Shortcuts are updated from another thread, so QML polls this to get notified
+    /// on the Qt thread.
+    fn refresh_shortcuts(&mut self) {
+        if let Some(rx) = self.bound_shortcuts_rx.as_mut()
+            && rx.has_changed().unwrap_or(false)
+        {
+            rx.mark_unchanged();
+            self.shortcuts_changed();
+        }
+    }
+
+    fn configure_shortcuts(&self) {
+        futures::executor::block_on(async {
+            self.action_event_tx
+                .as_ref()
+                .unwrap()
+                .send(ActionEvent::ConfigureShortcuts)
+                .await
+                .unwrap();
+        })
+    }
+
+    /// Empty when shortcuts work through the portal, so QML can hide the message.
+    fn shortcuts_status_text(&self) -> QString {
+        let status = futures::executor::block_on(async { *self.shortcuts_status.read().await });
+
+        match status {
+            ShortcutsStatus::Portal => QString::default(),
+            status => status.to_string().into(),
+        }
+    }
+
     fn apply_config(&self) {
         let mut config = futures::executor::block_on(async { self.config.write().await });
         config.framerate = self.framerate;
@@ -171,7 +292,13 @@ impl Settings {
         self.change();
     }
 
-    pub async fn new(config: Arc<RwLock<Config>>, action_event_tx: Sender<ActionEvent>) -> Self {
+    pub async fn new(
+        config: Arc<RwLock<Config>>,
+        recent_replays: Arc<RwLock<RecentReplays>>,
+        action_event_tx: Sender<ActionEvent>,
+        shortcuts_status: Arc<RwLock<ShortcutsStatus>>,
+        bound_shortcuts_rx: watch::Receiver<Vec<BoundShortcut>>,
+    ) -> Self {
         let config_values = config.read().await;
 
         let audio_applications =
@@ -232,7 +359,20 @@ impl Settings {
             remove_audio_track: Default::default(),
             add_audio_track: Default::default(),
             move_audio_track: Default::default(),
+            shortcuts_status_text: Default::default(),
+            refresh_shortcuts: Default::default(),
+            configure_shortcuts: Default::default(),
+            shortcuts: Default::default(),
+            shortcuts_changed: Default::default(),
+            retention_max_size_gb: config_values.retention.max_total_size_gb.unwrap_or(0) as u32,
+            retention_max_age_days: config_values.retention.max_age_days.unwrap_or(0),
+            retention_max_count: config_values.retention.max_count_per_app.unwrap_or(0) as u32,
+            retention_preview: Default::default(),
+            apply_retention: Default::default(),
             config: config.clone(),
+            recent_replays,
+            shortcuts_status,
+            bound_shortcuts_rx: Some(bound_shortcuts_rx),
             action_event_tx: Some(action_event_tx),
         }
     }
@@ -1,53 +1,61 @@
-use std::{path::PathBuf, process::Stdio, str::FromStr};
+use std::{
+    path::{Path, PathBuf},
+    process::Stdio,
+};
 
-use ashpd::desktop::file_chooser::OpenFileRequest;
+use ashpd::desktop::{
+    file_chooser::OpenFileRequest,
+    open_uri::{self, OpenDirectoryRequest},
+    trash,
+};
 use time::OffsetDateTime;
 
-pub fn get_app_name(desktop_file: &str) -> Result<Option<String>, std::io::Error> {
-    let user_applications_path = format!("{}/applications/", dirs::data_dir().unwrap().display());
-    let search_paths = vec![
-        "/usr/share/applications/",
-        &user_applications_path,
-        "/var/lib/flatpak/exports/share/applications/",
-        "",
-    ];
-    let desktop_file_path = search_paths.iter().fold(None, |acc, search_path| {
-        let path = format!("{}{}.desktop", search_path, desktop_file);
-        if std::fs::exists(&path).unwrap() {
-            Some(path)
-        } else {
-            acc
-        }
-    });
-
-    Ok(if let Some(desktop_file_path) = desktop_file_path {
-        let parsed = freedesktop_entry_parser::parse_entry(desktop_file_path)?;
-        Some(
-            parsed
-                .section("Desktop Entry")
-                .ok_or(std::io::ErrorKind::InvalidData)?
-                .attr("Name")[0]
-                .clone(),
-        )
-    } else {
-        None
-    })
+/// Directory of `trayplay.toml`. Tests get a temporary directory instead of the user's,
+/// without changing the environment other threads read.
+#[cfg(not(test))]
+pub fn config_dir() -> PathBuf {
+    dirs::config_dir().unwrap()
+}
+
+/// Directory the recent replays list, retention ledger and log are written to, in a
+/// `trayplay` subdirectory.
+#[cfg(not(test))]
+pub fn data_dir() -> PathBuf {
+    dirs::data_dir().unwrap()
 }
 
-pub fn get_script_path() -> Option<PathBuf> {
-    let local_path = std::env::current_dir().unwrap().join("dist/kwin_script.js");
-    let search_paths = vec![
-        "/usr/share/trayplay/kwin_script.js",
-        local_path.to_str().unwrap(),
-    ];
+/// Default replay directory.
+#[cfg(not(test))]
+pub fn video_dir() -> PathBuf {
+    dirs::video_dir().unwrap()
+}
 
-    search_paths.iter().fold(None, |acc, search_path| {
-        if std::fs::exists(search_path).unwrap() {
-            Some(PathBuf::from_str(search_path).unwrap())
-        } else {
-            acc
-        }
+#[cfg(test)]
+pub fn config_dir() -> PathBuf {
+    test_home().join("config")
+}
+
+#[cfg(test)]
+pub fn data_dir() -> PathBuf {
+    test_home().join("data")
+}
+
+#[cfg(test)]
+pub fn video_dir() -> PathBuf {
+    test_home().join("videos")
+}
+
+/// Temporary home shared by all tests of a run.
+#[cfg(test)]
+fn test_home() -> &'static Path {
+    static HOME: std::sync::OnceLock<tempfile::TempDir> = std::sync::OnceLock::new();
+
+    HOME.get_or_init(|| {
+        let home = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(home.path().join("config")).unwrap();
+        home
     })
+    .path()
 }
 
 pub async fn ask_path(
@@ -76,6 +84,58 @@ pub async fn ask_path(
     }
 }
 
+pub async fn open_file(path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let file = std::fs::File::open(path)?;
+    open_uri::OpenFileRequest::default()
+        .send_file(&file)
+        .await?;
+
+    Ok(())
+}
+
+/// Opens the directory containing `path` in the file manager, highlighting the file if possible.
+pub async fn show_in_folder(path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let file = std::fs::File::open(path)?;
+    OpenDirectoryRequest::default().send(&file).await?;
+
+    Ok(())
+}
+
+pub async fn trash_file(path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let file = std::fs::File::open(path)?;
+    trash::trash_file(&file).await?;
+
+    Ok(())
+}
+
+/// Free space in bytes on the filesystem `path` is (or would be created) on.
+pub fn free_space(path: &Path) -> nix::Result<u64> {
+    // The replay directory may not exist yet, its closest existing parent decides
+    let existing = path
+        .ancestors()
+        .find(|ancestor| ancestor.exists())
+        .unwrap_or(Path::new("/"));
+    let stat = nix::sys::statvfs::statvfs(existing)?;
+
+    Ok(stat.blocks_available() as u64 * stat.fragment_size() as u64)
+}
+
+/// Fails when a file of `needed` bytes wouldn't fit into `directory`, so ffmpeg doesn't
+/// leave a truncated file behind.
+pub fn check_free_space(directory: &Path, needed: u64) -> Result<(), String> {
+    let free = free_space(directory).map_err(|err| err.to_string())?;
+    if free < needed {
+        return Err(format!(
+            "not enough free space in {}, {} MB needed but only {} MB left",
+            directory.display(),
+            needed / 1024 / 1024,
+            free / 1024 / 1024
+        ));
+    }
+
+    Ok(())
+}
+
 pub fn process_pattern(pattern: &str, app_name: &str) -> String {
     let local_time = OffsetDateTime::now_local().unwrap();
 
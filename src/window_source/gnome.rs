@@ -0,0 +1,71 @@
+use std::collections::HashMap;
+
+use futures::future::BoxFuture;
+use log::info;
+use tokio::sync::mpsc;
+use zbus::{Connection, proxy, zvariant::OwnedValue};
+
+use super::{ActiveWindowSource, SourceError, has_owner};
+use crate::active_window::ActiveWindow;
+
+/// UUID of the extension in `dist/gnome-extension`
+const EXTENSION_UUID: &str = "trayplay@kabus.ovh";
+
+#[proxy(
+    interface = "org.gnome.Shell.Extensions",
+    default_service = "org.gnome.Shell",
+    default_path = "/org/gnome/Shell"
+)]
+trait ShellExtensions {
+    fn get_extension_info(&self, uuid: &str) -> zbus::Result<HashMap<String, OwnedValue>>;
+
+    fn enable_extension(&self, uuid: &str) -> zbus::Result<bool>;
+}
+
+/// GNOME Shell doesn't let other programs see windows, so this relies on the TrayPlay
+/// extension, which reports them like the KWin script does.
+pub struct GnomeShellSource {
+    dbus_connection: Connection,
+}
+
+impl GnomeShellSource {
+    pub fn new(dbus_connection: Connection) -> Self {
+        Self { dbus_connection }
+    }
+}
+
+impl ActiveWindowSource for GnomeShellSource {
+    fn name(&self) -> &'static str {
+        "GNOME Shell"
+    }
+
+    fn is_available(&self) -> BoxFuture<'_, bool> {
+        Box::pin(has_owner(&self.dbus_connection, "org.gnome.Shell"))
+    }
+
+    fn start(
+        &mut self,
+        _window_tx: mpsc::Sender<ActiveWindow>,
+    ) -> BoxFuture<'_, Result<(), SourceError>> {
+        Box::pin(async move {
+            let extensions = ShellExtensionsProxy::new(&self.dbus_connection).await?;
+
+            if extensions
+                .get_extension_info(EXTENSION_UUID)
+                .await?
+                .is_empty()
+            {
+                return Err(format!(
+                    "The {} GNOME Shell extension isn't installed, replays won't be named after the active app.",
+                    EXTENSION_UUID
+                )
+                .into());
+            }
+
+            info!("Enabling GNOME Shell extension {}", EXTENSION_UUID);
+            extensions.enable_extension(EXTENSION_UUID).await?;
+
+            Ok(())
+        })
+    }
+}
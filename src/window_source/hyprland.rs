@@ -0,0 +1,303 @@
+use std::path::{Path, PathBuf};
+
+use futures::future::BoxFuture;
+use log::{debug, info};
+use serde::Deserialize;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::UnixStream,
+    sync::mpsc,
+};
+
+use super::{ActiveWindowSource, SourceError};
+use crate::active_window::{ActiveWindow, WindowGeometry};
+
+/// Events after which the active window is asked for again
+const WINDOW_EVENTS: [&str; 3] = ["activewindowv2", "fullscreen", "windowtitlev2"];
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "camelCase")]
+struct HyprlandWindow {
+    class: String,
+    title: String,
+    pid: i32,
+    at: [f64; 2],
+    size: [f64; 2],
+    fullscreen: Fullscreen,
+    monitor: i64,
+}
+
+/// Older Hyprland versions use a flag, newer ones a mode where 2 means fullscreen
+/// and 1 only maximized.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Fullscreen {
+    Flag(bool),
+    Mode(u8),
+}
+
+impl Default for Fullscreen {
+    fn default() -> Self {
+        Self::Flag(false)
+    }
+}
+
+#[derive(Deserialize)]
+struct HyprlandMonitor {
+    id: i64,
+    name: String,
+}
+
+/// Follows the active window through Hyprland's IPC sockets.
+pub struct HyprlandSource {
+    /// Directory with `.socket.sock` (requests) and `.socket2.sock` (events)
+    socket_dir: Option<PathBuf>,
+}
+
+impl HyprlandSource {
+    pub fn new(socket_dir: PathBuf) -> Self {
+        Self {
+            socket_dir: Some(socket_dir),
+        }
+    }
+
+    /// Uses the instance TrayPlay was started in.
+    pub fn from_env() -> Self {
+        std::env::var("HYPRLAND_INSTANCE_SIGNATURE")
+            .ok()
+            .zip(dirs::runtime_dir())
+            .map(|(signature, runtime_dir)| Self::new(runtime_dir.join("hypr").join(signature)))
+            .unwrap_or(Self { socket_dir: None })
+    }
+}
+
+impl ActiveWindowSource for HyprlandSource {
+    fn name(&self) -> &'static str {
+        "Hyprland"
+    }
+
+    fn is_available(&self) -> BoxFuture<'_, bool> {
+        Box::pin(async move {
+            self.socket_dir
+                .as_ref()
+                .is_some_and(|socket_dir| socket_dir.join(".socket2.sock").exists())
+        })
+    }
+
+    fn start(
+        &mut self,
+        window_tx: mpsc::Sender<ActiveWindow>,
+    ) -> BoxFuture<'_, Result<(), SourceError>> {
+        Box::pin(async move {
+            let socket_dir = self
+                .socket_dir
+                .clone()
+                .ok_or("Hyprland isn't running, HYPRLAND_INSTANCE_SIGNATURE is not set")?;
+            let events = UnixStream::connect(socket_dir.join(".socket2.sock")).await?;
+
+            tokio::spawn(async move {
+                send_active_window(&socket_dir, &window_tx).await;
+
+                let mut lines = BufReader::new(events).lines();
+                while let Ok(Some(line)) = lines.next_line().await {
+                    let Some((event, _)) = line.split_once(">>") else {
+                        continue;
+                    };
+
+                    if WINDOW_EVENTS.contains(&event) {
+                        send_active_window(&socket_dir, &window_tx).await;
+                    }
+                }
+
+                info!("Lost connection to Hyprland");
+            });
+
+            Ok(())
+        })
+    }
+}
+
+async fn send_active_window(socket_dir: &Path, window_tx: &mpsc::Sender<ActiveWindow>) {
+    let window = match request::<HyprlandWindow>(socket_dir, "j/activewindow").await {
+        Ok(window) => window,
+        Err(err) => {
+            debug!("Cannot get active window from Hyprland: {}", err);
+            return;
+        }
+    };
+    let monitors = request::<Vec<HyprlandMonitor>>(socket_dir, "j/monitors")
+        .await
+        .unwrap_or_default();
+
+    let _ = window_tx.send(active_window(window, &monitors)).await;
+}
+
+fn active_window(window: HyprlandWindow, monitors: &[HyprlandMonitor]) -> ActiveWindow {
+    let output = monitors
+        .iter()
+        .find(|monitor| monitor.id == window.monitor)
+        .map(|monitor| monitor.name.clone())
+        .unwrap_or_default();
+
+    ActiveWindow {
+        desktop_file: window.class.clone(),
+        resource_class: window.class,
+        title: window.title,
+        fullscreen: match window.fullscreen {
+            Fullscreen::Flag(fullscreen) => fullscreen,
+            Fullscreen::Mode(mode) => mode >= 2,
+        },
+        geometry: WindowGeometry {
+            x: window.at[0],
+            y: window.at[1],
+            width: window.size[0],
+            height: window.size[1],
+        },
+        output,
+        pid: window.pid,
+        ..Default::default()
+    }
+}
+
+/// Sends a single request over the request socket, which Hyprland closes after replying.
+async fn request<T: for<'de> Deserialize<'de>>(
+    socket_dir: &Path,
+    command: &str,
+) -> Result<T, SourceError> {
+    let mut socket = UnixStream::connect(socket_dir.join(".socket.sock")).await?;
+    socket.write_all(command.as_bytes()).await?;
+
+    let mut reply = String::new();
+    socket.read_to_string(&mut reply).await?;
+
+    Ok(serde_json::from_str(&reply)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::net::UnixListener;
+
+    use super::*;
+
+    const MONITORS: &str = r#"[{"id": 0, "name": "DP-1"}, {"id": 1, "name": "HDMI-A-1"}]"#;
+
+    fn window(json: &str) -> ActiveWindow {
+        active_window(
+            serde_json::from_str(json).unwrap(),
+            &serde_json::from_str::<Vec<HyprlandMonitor>>(MONITORS).unwrap(),
+        )
+    }
+
+    #[test]
+    fn fullscreen_flag() {
+        assert!(window(r#"{"fullscreen": true}"#).fullscreen);
+        assert!(!window(r#"{"fullscreen": false}"#).fullscreen);
+    }
+
+    #[test]
+    fn fullscreen_mode() {
+        assert!(window(r#"{"fullscreen": 2}"#).fullscreen);
+        // Maximized
+        assert!(!window(r#"{"fullscreen": 1}"#).fullscreen);
+        assert!(!window(r#"{"fullscreen": 0}"#).fullscreen);
+    }
+
+    #[test]
+    fn window_fields() {
+        let window = window(
+            r#"{
+                "class": "cs2",
+                "title": "Counter-Strike 2",
+                "pid": 4321,
+                "at": [1920, 0],
+                "size": [2560, 1440],
+                "fullscreen": 2,
+                "monitor": 1,
+                "floating": false
+            }"#,
+        );
+
+        assert_eq!(window.desktop_file, "cs2");
+        assert_eq!(window.resource_class, "cs2");
+        assert_eq!(window.title, "Counter-Strike 2");
+        assert_eq!(window.pid, 4321);
+        assert_eq!(window.geometry.x, 1920.0);
+        assert_eq!(window.geometry.width, 2560.0);
+        assert_eq!(window.output, "HDMI-A-1");
+    }
+
+    #[test]
+    fn no_active_window() {
+        // Hyprland replies with an empty object when nothing is focused
+        let window = window("{}");
+
+        assert!(window.title.is_empty());
+        assert!(!window.fullscreen);
+        assert_eq!(window.output, "DP-1");
+    }
+
+    async fn recv(window_rx: &mut mpsc::Receiver<ActiveWindow>) -> ActiveWindow {
+        tokio::time::timeout(Duration::from_secs(5), window_rx.recv())
+            .await
+            .unwrap()
+            .unwrap()
+    }
+
+    /// Answers requests like Hyprland's `.socket.sock` does, one per connection.
+    fn serve_requests(listener: UnixListener, active_window: &'static str) {
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut command = [0; 64];
+                let length = socket.read(&mut command).await.unwrap();
+                let reply = match &command[..length] {
+                    b"j/activewindow" => active_window,
+                    b"j/monitors" => MONITORS,
+                    _ => "unknown request",
+                };
+                socket.write_all(reply.as_bytes()).await.unwrap();
+            }
+        });
+    }
+
+    #[tokio::test]
+    async fn follows_events() {
+        let socket_dir = tempfile::tempdir().unwrap();
+        serve_requests(
+            UnixListener::bind(socket_dir.path().join(".socket.sock")).unwrap(),
+            r#"{"class": "cs2", "title": "Counter-Strike 2", "fullscreen": 2, "monitor": 1}"#,
+        );
+        let events = UnixListener::bind(socket_dir.path().join(".socket2.sock")).unwrap();
+
+        let mut source = HyprlandSource::new(socket_dir.path().to_path_buf());
+        assert!(source.is_available().await);
+
+        let (window_tx, mut window_rx) = mpsc::channel(8);
+        source.start(window_tx).await.unwrap();
+        let (mut events, _) = events.accept().await.unwrap();
+
+        // The window active when the source starts
+        let window = recv(&mut window_rx).await;
+        assert_eq!(window.title, "Counter-Strike 2");
+        assert!(window.fullscreen);
+        assert_eq!(window.output, "HDMI-A-1");
+
+        events
+            .write_all(b"workspacev2>>1,1\nactivewindowv2>>5599c0a9e6f0\n")
+            .await
+            .unwrap();
+        assert_eq!(recv(&mut window_rx).await.resource_class, "cs2");
+        // Events not about the active window are skipped
+        assert!(window_rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn unavailable_without_sockets() {
+        let socket_dir = tempfile::tempdir().unwrap();
+        let mut source = HyprlandSource::new(socket_dir.path().to_path_buf());
+
+        assert!(!source.is_available().await);
+        assert!(source.start(mpsc::channel(1).0).await.is_err());
+    }
+}
@@ -0,0 +1,229 @@
+use std::{fmt::Display, path::PathBuf};
+
+use futures::future::BoxFuture;
+use futures_util::StreamExt;
+use log::{error, info, warn};
+use tokio::sync::mpsc;
+use zbus::{Connection, object_server::Interface, proxy};
+
+use super::{ActiveWindowSource, SourceError, has_owner};
+use crate::{
+    active_window::{ActiveWindow, ActiveWindowManager},
+    dbus,
+};
+
+/// The script is built in, so it's found no matter where TrayPlay is started from.
+const SCRIPT: &str = include_str!("../../dist/kwin_script.js");
+
+/// Name the script is loaded under. It stays the same between runs, so a script left
+/// behind by a crashed instance can be found and replaced.
+const SCRIPT_PLUGIN_NAME: &str = "TrayPlay";
+
+#[proxy(
+    interface = "org.kde.kwin.Scripting",
+    default_service = "org.kde.KWin",
+    default_path = "/Scripting"
+)]
+trait KWinScripting {
+    #[zbus(name = "loadScript")]
+    fn load_script(&self, file_path: &str, plugin_name: &str) -> zbus::Result<i32>;
+
+    #[zbus(name = "unloadScript")]
+    fn unload_script(&self, plugin_name: &str) -> zbus::Result<bool>;
+
+    #[zbus(name = "isScriptLoaded")]
+    fn is_script_loaded(&self, plugin_name: &str) -> zbus::Result<bool>;
+
+    #[zbus(name = "start")]
+    fn start(&self) -> zbus::Result<()>;
+}
+
+#[derive(Debug)]
+pub enum Error {
+    /// Reading the overriding script or writing it for KWin failed
+    ScriptNotWritten(std::io::Error),
+    /// KWin refused to load the script
+    ScriptRejected,
+    DBus(zbus::Error),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::ScriptNotWritten(err) => write!(
+                f,
+                "Cannot prepare the KWin script, replays won't be named after the active app: {}",
+                err
+            ),
+            Self::ScriptRejected => write!(
+                f,
+                "KWin refused to load the TrayPlay script, replays won't be named after the active app."
+            ),
+            Self::DBus(err) => write!(
+                f,
+                "Cannot talk to KWin, replays won't be named after the active app: {}",
+                err
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<zbus::Error> for Error {
+    fn from(value: zbus::Error) -> Self {
+        Self::DBus(value)
+    }
+}
+
+pub struct KWinScriptManager {
+    dbus_connection: Connection,
+    /// Used instead of the built-in script when set
+    script_override: Option<PathBuf>,
+}
+
+impl KWinScriptManager {
+    pub fn new(dbus_connection: Connection, script_override: Option<PathBuf>) -> Self {
+        Self {
+            dbus_connection,
+            script_override,
+        }
+    }
+
+    pub async fn load(&self) -> Result<(), Error> {
+        let proxy = KWinScriptingProxy::new(&self.dbus_connection).await?;
+        load_script(&proxy, self.script_override.as_ref()).await
+    }
+
+    pub async fn unload(&self) -> Result<(), Error> {
+        info!("Unloading KWin script");
+
+        KWinScriptingProxy::new(&self.dbus_connection)
+            .await?
+            .unload_script(SCRIPT_PLUGIN_NAME)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Loads the script again whenever KWin restarts, as the new instance doesn't have it.
+    pub async fn watch_kwin_restarts(&self) -> Result<(), Error> {
+        let proxy = KWinScriptingProxy::new(&self.dbus_connection).await?;
+        let script_override = self.script_override.clone();
+        let mut owner_changes = proxy.inner().receive_owner_changed().await?;
+
+        tokio::spawn(async move {
+            while let Some(owner) = owner_changes.next().await {
+                if owner.is_none() {
+                    info!("KWin has stopped");
+                    continue;
+                }
+
+                info!("KWin has restarted");
+                if let Err(err) = load_script(&proxy, script_override.as_ref()).await {
+                    error!("{}", err);
+                }
+            }
+        });
+
+        Ok(())
+    }
+}
+
+impl ActiveWindowSource for KWinScriptManager {
+    fn name(&self) -> &'static str {
+        "KWin"
+    }
+
+    fn is_available(&self) -> BoxFuture<'_, bool> {
+        Box::pin(has_owner(&self.dbus_connection, "org.kde.KWin"))
+    }
+
+    /// The script reports windows through the `ActiveWindowManager` D-Bus interface.
+    ///
+    /// Restarts are watched before loading, so a KWin that isn't ready yet or rejects the
+    /// script gets another chance when it comes back.
+    fn start(
+        &mut self,
+        _window_tx: mpsc::Sender<ActiveWindow>,
+    ) -> BoxFuture<'_, Result<(), SourceError>> {
+        Box::pin(async move {
+            let watching = match self.watch_kwin_restarts().await {
+                Ok(()) => true,
+                Err(err) => {
+                    warn!("Cannot watch for KWin restarts: {}", err);
+                    false
+                }
+            };
+
+            match self.load().await {
+                Ok(()) => {}
+                Err(err) if watching => warn!("{}", err),
+                Err(err) => return Err(err.into()),
+            }
+
+            Ok(())
+        })
+    }
+
+    fn stop(&mut self) -> BoxFuture<'_, ()> {
+        Box::pin(async move {
+            if let Err(err) = self.unload().await {
+                warn!("Failed to unload KWin script: {}", err);
+            }
+        })
+    }
+}
+
+async fn load_script(
+    proxy: &KWinScriptingProxy<'_>,
+    script_override: Option<&PathBuf>,
+) -> Result<(), Error> {
+    info!("Loading KWin script");
+
+    if proxy.is_script_loaded(SCRIPT_PLUGIN_NAME).await? {
+        info!("Unloading KWin script left by a previous instance");
+        proxy.unload_script(SCRIPT_PLUGIN_NAME).await?;
+    }
+
+    let script_path = write_script(script_override)
+        .await
+        .map_err(Error::ScriptNotWritten)?;
+    let script_id = proxy
+        .load_script(&script_path.to_string_lossy(), SCRIPT_PLUGIN_NAME)
+        .await?;
+    if script_id < 0 {
+        return Err(Error::ScriptRejected);
+    }
+
+    proxy.start().await?;
+
+    Ok(())
+}
+
+/// Writes the script with D-Bus names of the active window service filled in to the
+/// runtime directory, as KWin only loads scripts from files.
+async fn write_script(script_override: Option<&PathBuf>) -> Result<PathBuf, std::io::Error> {
+    let script = match script_override {
+        Some(path) => {
+            info!("Using KWin script from {}", path.display());
+            tokio::fs::read_to_string(path).await?
+        }
+        None => SCRIPT.to_string(),
+    };
+    let script = script
+        .replace("%SERVICE%", dbus::SERVICE_NAME)
+        .replace("%OBJECT_PATH%", dbus::OBJECT_PATH)
+        .replace("%INTERFACE%", &ActiveWindowManager::name())
+        .replace("%METHOD%", dbus::SET_ACTIVE_WINDOW_METHOD);
+
+    let directory = dirs::runtime_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join("trayplay");
+    tokio::fs::create_dir_all(&directory).await?;
+
+    let path = directory.join("kwin_script.js");
+    tokio::fs::write(&path, script).await?;
+
+    Ok(path)
+}
@@ -0,0 +1,95 @@
+use std::path::PathBuf;
+
+use futures::future::BoxFuture;
+use tokio::sync::mpsc;
+use zbus::{Connection, names::WellKnownName};
+
+use crate::{active_window::ActiveWindow, config::WindowSourceKind};
+
+pub use gnome::GnomeShellSource;
+pub use hyprland::HyprlandSource;
+pub use kwin::KWinScriptManager;
+pub use wlroots::WlrootsSource;
+pub use x11::X11Source;
+
+mod gnome;
+mod hyprland;
+mod kwin;
+mod wlroots;
+mod x11;
+
+pub type SourceError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Tells TrayPlay which window is active, usually by asking the compositor.
+pub trait ActiveWindowSource: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Whether the source can work in the current session.
+    fn is_available(&self) -> BoxFuture<'_, bool>;
+
+    /// Starts reporting the active window to `window_tx` whenever it changes. Sources that
+    /// live inside the compositor report through the `ActiveWindowManager` D-Bus
+    /// interface instead.
+    fn start(
+        &mut self,
+        window_tx: mpsc::Sender<ActiveWindow>,
+    ) -> BoxFuture<'_, Result<(), SourceError>>;
+
+    /// Cleans up before TrayPlay quits.
+    fn stop(&mut self) -> BoxFuture<'_, ()> {
+        Box::pin(async {})
+    }
+}
+
+/// Picks the source for `kind`. With [`WindowSourceKind::Auto`] the first available one
+/// is used, a source chosen explicitly is used even when it doesn't look available.
+pub async fn select(
+    kind: WindowSourceKind,
+    kwin_script_path: Option<PathBuf>,
+    connection: &Connection,
+) -> Option<Box<dyn ActiveWindowSource>> {
+    let kwin = || -> Box<dyn ActiveWindowSource> {
+        Box::new(KWinScriptManager::new(
+            connection.clone(),
+            kwin_script_path.clone(),
+        ))
+    };
+    let gnome =
+        || -> Box<dyn ActiveWindowSource> { Box::new(GnomeShellSource::new(connection.clone())) };
+
+    let mut candidates = match kind {
+        WindowSourceKind::Auto => vec![
+            kwin(),
+            gnome(),
+            Box::new(HyprlandSource::from_env()),
+            Box::new(WlrootsSource),
+            Box::new(X11Source),
+        ],
+        WindowSourceKind::Kwin => return Some(kwin()),
+        WindowSourceKind::GnomeShell => return Some(gnome()),
+        WindowSourceKind::Hyprland => return Some(Box::new(HyprlandSource::from_env())),
+        WindowSourceKind::Wlroots => return Some(Box::new(WlrootsSource)),
+        WindowSourceKind::X11 => return Some(Box::new(X11Source)),
+        WindowSourceKind::None => return None,
+    };
+
+    for source in candidates.drain(..) {
+        if source.is_available().await {
+            return Some(source);
+        }
+    }
+
+    None
+}
+
+/// Whether something owns `name` on the session bus.
+async fn has_owner(connection: &Connection, name: &'static str) -> bool {
+    let Ok(proxy) = zbus::fdo::DBusProxy::new(connection).await else {
+        return false;
+    };
+
+    proxy
+        .name_has_owner(WellKnownName::from_static_str_unchecked(name).into())
+        .await
+        .unwrap_or(false)
+}
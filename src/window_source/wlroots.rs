@@ -0,0 +1,293 @@
+use std::collections::HashMap;
+
+use futures::future::BoxFuture;
+use log::{debug, info};
+use tokio::sync::mpsc;
+use wayland_client::{
+    Connection, Dispatch, EventQueue, Proxy, QueueHandle,
+    backend::ObjectId,
+    event_created_child,
+    globals::{GlobalList, GlobalListContents, registry_queue_init},
+    protocol::{wl_output::WlOutput, wl_registry::WlRegistry},
+};
+use wayland_protocols_wlr::foreign_toplevel::v1::client::{
+    zwlr_foreign_toplevel_handle_v1::{self, ZwlrForeignToplevelHandleV1},
+    zwlr_foreign_toplevel_manager_v1::{self, ZwlrForeignToplevelManagerV1},
+};
+
+use super::{ActiveWindowSource, SourceError};
+use crate::active_window::ActiveWindow;
+
+const MANAGER_INTERFACE: &str = "zwlr_foreign_toplevel_manager_v1";
+
+/// Follows the active window of compositors implementing `wlr-foreign-toplevel-management`
+/// (Sway, niri, Wayfire, labwc and others). The protocol doesn't expose process IDs or
+/// window positions, so those stay empty.
+pub struct WlrootsSource;
+
+impl ActiveWindowSource for WlrootsSource {
+    fn name(&self) -> &'static str {
+        "wlr-foreign-toplevel-management"
+    }
+
+    fn is_available(&self) -> BoxFuture<'_, bool> {
+        Box::pin(async {
+            if std::env::var_os("WAYLAND_DISPLAY").is_none() {
+                return false;
+            }
+
+            tokio::task::spawn_blocking(|| {
+                connect().is_ok_and(|(_, globals, _)| {
+                    globals.contents().with_list(|list| {
+                        list.iter()
+                            .any(|global| global.interface == MANAGER_INTERFACE)
+                    })
+                })
+            })
+            .await
+            .unwrap_or(false)
+        })
+    }
+
+    fn start(
+        &mut self,
+        window_tx: mpsc::Sender<ActiveWindow>,
+    ) -> BoxFuture<'_, Result<(), SourceError>> {
+        Box::pin(async move {
+            let (connection, globals, mut event_queue) = connect()?;
+            let queue_handle = event_queue.handle();
+
+            globals.bind::<ZwlrForeignToplevelManagerV1, _, _>(&queue_handle, 1..=3, ())?;
+            // Output names arrived in version 4
+            let registry = globals.registry();
+            globals.contents().with_list(|list| {
+                for global in list.iter().filter(|global| global.interface == "wl_output") {
+                    if global.version >= 4 {
+                        registry.bind::<WlOutput, _, _>(global.name, 4, &queue_handle, ());
+                    }
+                }
+            });
+
+            // Dispatching blocks, so the connection lives on its own thread
+            std::thread::spawn(move || {
+                let _connection = connection;
+                let mut state = WlrootsState {
+                    window_tx,
+                    toplevels: HashMap::new(),
+                    outputs: HashMap::new(),
+                };
+
+                while !state.window_tx.is_closed() {
+                    if let Err(err) = event_queue.blocking_dispatch(&mut state) {
+                        info!("Lost connection to the compositor: {}", err);
+                        break;
+                    }
+                }
+            });
+
+            Ok(())
+        })
+    }
+}
+
+fn connect() -> Result<(Connection, GlobalList, EventQueue<WlrootsState>), SourceError> {
+    let connection = Connection::connect_to_env()?;
+    let (globals, event_queue) = registry_queue_init::<WlrootsState>(&connection)?;
+
+    Ok((connection, globals, event_queue))
+}
+
+/// Toplevel properties are sent one by one and only apply after `done`.
+#[derive(Default)]
+struct Toplevel {
+    title: String,
+    app_id: String,
+    activated: bool,
+    fullscreen: bool,
+    output: Option<ObjectId>,
+}
+
+impl Toplevel {
+    fn active_window(&self, output: String) -> ActiveWindow {
+        ActiveWindow {
+            desktop_file: self.app_id.clone(),
+            resource_class: self.app_id.clone(),
+            title: self.title.clone(),
+            fullscreen: self.fullscreen,
+            output,
+            ..Default::default()
+        }
+    }
+}
+
+/// The `state` event carries an array of native-endian `u32`s.
+fn decode_states(state: &[u8]) -> Vec<u32> {
+    state
+        .chunks_exact(4)
+        .map(|chunk| u32::from_ne_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+        .collect()
+}
+
+struct WlrootsState {
+    window_tx: mpsc::Sender<ActiveWindow>,
+    toplevels: HashMap<ObjectId, Toplevel>,
+    outputs: HashMap<ObjectId, String>,
+}
+
+impl Dispatch<WlRegistry, GlobalListContents> for WlrootsState {
+    fn event(
+        _state: &mut Self,
+        _registry: &WlRegistry,
+        _event: <WlRegistry as Proxy>::Event,
+        _data: &GlobalListContents,
+        _connection: &Connection,
+        _queue_handle: &QueueHandle<Self>,
+    ) {
+        // Outputs connected later aren't bound, windows on them just have no output name
+    }
+}
+
+impl Dispatch<WlOutput, ()> for WlrootsState {
+    fn event(
+        state: &mut Self,
+        output: &WlOutput,
+        event: <WlOutput as Proxy>::Event,
+        _data: &(),
+        _connection: &Connection,
+        _queue_handle: &QueueHandle<Self>,
+    ) {
+        if let wayland_client::protocol::wl_output::Event::Name { name } = event {
+            state.outputs.insert(output.id(), name);
+        }
+    }
+}
+
+impl Dispatch<ZwlrForeignToplevelManagerV1, ()> for WlrootsState {
+    fn event(
+        state: &mut Self,
+        _manager: &ZwlrForeignToplevelManagerV1,
+        event: zwlr_foreign_toplevel_manager_v1::Event,
+        _data: &(),
+        _connection: &Connection,
+        _queue_handle: &QueueHandle<Self>,
+    ) {
+        match event {
+            zwlr_foreign_toplevel_manager_v1::Event::Toplevel { toplevel } => {
+                state.toplevels.insert(toplevel.id(), Toplevel::default());
+            }
+            zwlr_foreign_toplevel_manager_v1::Event::Finished => {
+                debug!("Compositor stopped sending toplevels");
+            }
+            _ => {}
+        }
+    }
+
+    event_created_child!(WlrootsState, ZwlrForeignToplevelManagerV1, [
+        zwlr_foreign_toplevel_manager_v1::EVT_TOPLEVEL_OPCODE => (ZwlrForeignToplevelHandleV1, ())
+    ]);
+}
+
+impl Dispatch<ZwlrForeignToplevelHandleV1, ()> for WlrootsState {
+    fn event(
+        state: &mut Self,
+        handle: &ZwlrForeignToplevelHandleV1,
+        event: zwlr_foreign_toplevel_handle_v1::Event,
+        _data: &(),
+        _connection: &Connection,
+        _queue_handle: &QueueHandle<Self>,
+    ) {
+        if let zwlr_foreign_toplevel_handle_v1::Event::Closed = event {
+            state.toplevels.remove(&handle.id());
+            handle.destroy();
+            return;
+        }
+
+        let Some(toplevel) = state.toplevels.get_mut(&handle.id()) else {
+            return;
+        };
+
+        match event {
+            zwlr_foreign_toplevel_handle_v1::Event::Title { title } => toplevel.title = title,
+            zwlr_foreign_toplevel_handle_v1::Event::AppId { app_id } => toplevel.app_id = app_id,
+            zwlr_foreign_toplevel_handle_v1::Event::State { state } => {
+                let states = decode_states(&state);
+
+                toplevel.activated =
+                    states.contains(&(zwlr_foreign_toplevel_handle_v1::State::Activated as u32));
+                toplevel.fullscreen =
+                    states.contains(&(zwlr_foreign_toplevel_handle_v1::State::Fullscreen as u32));
+            }
+            zwlr_foreign_toplevel_handle_v1::Event::OutputEnter { output } => {
+                toplevel.output = Some(output.id());
+            }
+            zwlr_foreign_toplevel_handle_v1::Event::OutputLeave { output }
+                if toplevel.output == Some(output.id()) =>
+            {
+                toplevel.output = None;
+            }
+            zwlr_foreign_toplevel_handle_v1::Event::Done if toplevel.activated => {
+                let output = toplevel
+                    .output
+                    .as_ref()
+                    .and_then(|output| state.outputs.get(output))
+                    .cloned()
+                    .unwrap_or_default();
+
+                let _ = state
+                    .window_tx
+                    .blocking_send(toplevel.active_window(output));
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use zwlr_foreign_toplevel_handle_v1::State;
+
+    use super::*;
+
+    fn encode_states(states: &[State]) -> Vec<u8> {
+        states
+            .iter()
+            .flat_map(|state| (*state as u32).to_ne_bytes())
+            .collect()
+    }
+
+    #[test]
+    fn decodes_states() {
+        let states = decode_states(&encode_states(&[State::Activated, State::Fullscreen]));
+
+        assert_eq!(states, [State::Activated as u32, State::Fullscreen as u32]);
+        assert!(decode_states(&[]).is_empty());
+    }
+
+    #[test]
+    fn ignores_incomplete_state() {
+        let mut state = encode_states(&[State::Maximized]);
+        state.push(0);
+
+        assert_eq!(decode_states(&state), [State::Maximized as u32]);
+    }
+
+    #[test]
+    fn maps_toplevel() {
+        let toplevel = Toplevel {
+            title: "Minecraft 1.21".to_string(),
+            app_id: "com.mojang.minecraft".to_string(),
+            activated: true,
+            fullscreen: true,
+            output: None,
+        };
+        let window = toplevel.active_window("eDP-1".to_string());
+
+        assert_eq!(window.desktop_file, "com.mojang.minecraft");
+        assert_eq!(window.resource_class, "com.mojang.minecraft");
+        assert_eq!(window.title, "Minecraft 1.21");
+        assert!(window.fullscreen);
+        assert_eq!(window.output, "eDP-1");
+        // Not exposed by the protocol
+        assert_eq!(window.pid, 0);
+    }
+}
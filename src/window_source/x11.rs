@@ -0,0 +1,207 @@
+use futures::future::BoxFuture;
+use log::info;
+use tokio::sync::mpsc;
+use x11rb::{
+    atom_manager,
+    connection::Connection,
+    properties::WmClass,
+    protocol::{
+        Event,
+        xproto::{AtomEnum, ChangeWindowAttributesAux, ConnectionExt, EventMask, Window},
+    },
+    rust_connection::RustConnection,
+};
+
+use super::{ActiveWindowSource, SourceError};
+use crate::active_window::{ActiveWindow, WindowGeometry};
+
+atom_manager! {
+    Atoms: AtomsCookie {
+        _NET_ACTIVE_WINDOW,
+        _NET_WM_STATE,
+        _NET_WM_STATE_FULLSCREEN,
+        _NET_WM_PID,
+        _NET_WM_NAME,
+        UTF8_STRING,
+    }
+}
+
+/// Follows `_NET_ACTIVE_WINDOW` of EWMH window managers on X11. Under Wayland it would only
+/// see XWayland windows, so it's not picked there automatically.
+pub struct X11Source;
+
+impl ActiveWindowSource for X11Source {
+    fn name(&self) -> &'static str {
+        "X11"
+    }
+
+    fn is_available(&self) -> BoxFuture<'_, bool> {
+        Box::pin(async {
+            std::env::var_os("DISPLAY").is_some() && std::env::var_os("WAYLAND_DISPLAY").is_none()
+        })
+    }
+
+    fn start(
+        &mut self,
+        window_tx: mpsc::Sender<ActiveWindow>,
+    ) -> BoxFuture<'_, Result<(), SourceError>> {
+        Box::pin(async move {
+            let (connection, screen) = x11rb::connect(None)?;
+            let root = connection.setup().roots[screen].root;
+            let atoms = Atoms::new(&connection)?.reply()?;
+
+            connection.change_window_attributes(
+                root,
+                &ChangeWindowAttributesAux::new().event_mask(EventMask::PROPERTY_CHANGE),
+            )?;
+            connection.flush()?;
+
+            // Waiting for events blocks, so the connection lives on its own thread
+            std::thread::spawn(move || {
+                if let Err(err) = follow_active_window(&connection, root, &atoms, &window_tx) {
+                    info!("Lost connection to the X server: {}", err);
+                }
+            });
+
+            Ok(())
+        })
+    }
+}
+
+fn follow_active_window(
+    connection: &RustConnection,
+    root: Window,
+    atoms: &Atoms,
+    window_tx: &mpsc::Sender<ActiveWindow>,
+) -> Result<(), SourceError> {
+    let mut active = active_window(connection, root, atoms)?;
+    watch_window(connection, active)?;
+    send_window(connection, root, atoms, active, window_tx)?;
+
+    loop {
+        let Event::PropertyNotify(event) = connection.wait_for_event()? else {
+            continue;
+        };
+
+        if event.window == root && event.atom == atoms._NET_ACTIVE_WINDOW {
+            active = active_window(connection, root, atoms)?;
+            watch_window(connection, active)?;
+        } else if event.window != active
+            || (event.atom != atoms._NET_WM_STATE && event.atom != atoms._NET_WM_NAME)
+        {
+            continue;
+        }
+
+        if send_window(connection, root, atoms, active, window_tx).is_err() {
+            // TrayPlay is quitting
+            return Ok(());
+        }
+    }
+}
+
+fn active_window(
+    connection: &RustConnection,
+    root: Window,
+    atoms: &Atoms,
+) -> Result<Window, SourceError> {
+    Ok(connection
+        .get_property(
+            false,
+            root,
+            atoms._NET_ACTIVE_WINDOW,
+            AtomEnum::WINDOW,
+            0,
+            1,
+        )?
+        .reply()?
+        .value32()
+        .and_then(|mut value| value.next())
+        .unwrap_or_default())
+}
+
+/// Asks for fullscreen and title changes of `window`.
+fn watch_window(connection: &RustConnection, window: Window) -> Result<(), SourceError> {
+    // A window that is already gone only results in an error event, which is ignored
+    if window != 0 {
+        connection.change_window_attributes(
+            window,
+            &ChangeWindowAttributesAux::new().event_mask(EventMask::PROPERTY_CHANGE),
+        )?;
+        connection.flush()?;
+    }
+
+    Ok(())
+}
+
+fn send_window(
+    connection: &RustConnection,
+    root: Window,
+    atoms: &Atoms,
+    window: Window,
+    window_tx: &mpsc::Sender<ActiveWindow>,
+) -> Result<(), SourceError> {
+    let active_window = if window == 0 {
+        ActiveWindow::default()
+    } else {
+        // Windows can disappear at any moment, what's left unread just stays empty
+        read_window(connection, root, atoms, window).unwrap_or_default()
+    };
+
+    window_tx.blocking_send(active_window)?;
+
+    Ok(())
+}
+
+fn read_window(
+    connection: &RustConnection,
+    root: Window,
+    atoms: &Atoms,
+    window: Window,
+) -> Result<ActiveWindow, SourceError> {
+    let title = connection
+        .get_property(
+            false,
+            window,
+            atoms._NET_WM_NAME,
+            atoms.UTF8_STRING,
+            0,
+            1024,
+        )?
+        .reply()?
+        .value;
+    let class = WmClass::get(connection, window)?
+        .reply()?
+        .map(|class| String::from_utf8_lossy(class.class()).to_string())
+        .unwrap_or_default();
+    let pid = connection
+        .get_property(false, window, atoms._NET_WM_PID, AtomEnum::CARDINAL, 0, 1)?
+        .reply()?
+        .value32()
+        .and_then(|mut value| value.next())
+        .unwrap_or_default();
+    let fullscreen = connection
+        .get_property(false, window, atoms._NET_WM_STATE, AtomEnum::ATOM, 0, 32)?
+        .reply()?
+        .value32()
+        .is_some_and(|mut states| states.any(|state| state == atoms._NET_WM_STATE_FULLSCREEN));
+
+    let geometry = connection.get_geometry(window)?.reply()?;
+    let position = connection
+        .translate_coordinates(window, root, 0, 0)?
+        .reply()?;
+
+    Ok(ActiveWindow {
+        desktop_file: class.clone(),
+        resource_class: class,
+        title: String::from_utf8_lossy(&title).to_string(),
+        fullscreen,
+        geometry: WindowGeometry {
+            x: position.dst_x.into(),
+            y: position.dst_y.into(),
+            width: geometry.width.into(),
+            height: geometry.height.into(),
+        },
+        pid: pid as i32,
+        ..Default::default()
+    })
+}
@@ -10,6 +10,22 @@ import "components"
 Kirigami.ScrollablePage {
     id: mainPage
     title: qsTr("Settings")
+
+    header: Kirigami.InlineMessage {
+        id: shortcutsStatus
+        position: Kirigami.InlineMessage.Header
+        type: Kirigami.MessageType.Warning
+        showCloseButton: true
+        visible: text != ""
+
+        Connections {
+            target: window
+            function onVisibleChanged() {
+                shortcutsStatus.text = Settings.shortcuts_status_text();
+            }
+        }
+    }
+
     actions: [
         Kirigami.Action {
             id: recordReplays
@@ -241,6 +257,28 @@ Kirigami.ScrollablePage {
             }
         }
 
+        Item {}
+
+        Controls.Button {
+            Layout.fillWidth: true
+            text: qsTr("Edit shortcuts")
+            icon.name: "configure-shortcuts"
+            onClicked: function () {
+                window.pageStack.push(Qt.resolvedUrl("ShortcutsPage.qml"));
+            }
+        }
+
+        Item {}
+
+        Controls.Button {
+            Layout.fillWidth: true
+            text: qsTr("Storage cleanup")
+            icon.name: "edit-clear-history"
+            onClicked: function () {
+                window.pageStack.push(Qt.resolvedUrl("StoragePage.qml"));
+            }
+        }
+
         Item {
             Layout.columnSpan: 2
             Layout.fillHeight: true
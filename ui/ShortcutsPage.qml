@@ -0,0 +1,66 @@
+import QtQuick
+import QtQuick.Controls as Controls
+import QtQuick.Layouts
+import org.kde.kirigami as Kirigami
+import Settings
+
+Kirigami.Page {
+    title: qsTr("Shortcuts")
+    padding: 0
+    actions: [
+        Kirigami.Action {
+            icon.name: "configure-shortcuts"
+            text: qsTr("Change shortcuts")
+            onTriggered: function () {
+                Settings.configure_shortcuts();
+            }
+        }
+    ]
+
+    // Shortcuts are bound on another thread, check for changes while the page is open
+    Timer {
+        interval: 1000
+        running: true
+        repeat: true
+        onTriggered: function () {
+            Settings.refresh_shortcuts();
+        }
+    }
+
+    Controls.ScrollView {
+        anchors.fill: parent
+        Controls.ScrollBar.horizontal.policy: Controls.ScrollBar.AlwaysOff
+
+        ListView {
+            model: Settings.shortcuts
+
+            delegate: Controls.ItemDelegate {
+                width: ListView.view.width
+                contentItem: RowLayout {
+                    Controls.Label {
+                        Layout.fillWidth: true
+                        elide: Text.ElideRight
+                        text: modelData[0]
+                    }
+
+                    Controls.Label {
+                        opacity: 0.7
+                        text: modelData[1] || qsTr("Not assigned")
+                    }
+                }
+                onClicked: function () {
+                    Settings.configure_shortcuts();
+                }
+            }
+        }
+    }
+
+    Kirigami.PlaceholderMessage {
+        anchors.centerIn: parent
+        width: parent.width - Kirigami.Units.gridUnit * 4
+        visible: Settings.shortcuts.length == 0
+        icon.name: "configure-shortcuts"
+        text: qsTr("No global shortcuts are bound")
+        explanation: Settings.shortcuts_status_text()
+    }
+}
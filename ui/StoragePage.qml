@@ -0,0 +1,128 @@
+import QtQuick
+import QtQuick.Controls as Controls
+import QtQuick.Layouts
+import org.kde.kirigami as Kirigami
+import Settings
+import "components"
+
+Kirigami.ScrollablePage {
+    id: storagePage
+    title: qsTr("Storage Cleanup")
+
+    property var preview: []
+
+    function applyValues() {
+        Settings.retention_max_size_gb = maxSize.value;
+        Settings.retention_max_age_days = maxAge.value;
+        Settings.retention_max_count = maxCount.value;
+    }
+
+    ColumnLayout {
+        width: parent.width
+        spacing: Kirigami.Units.largeSpacing
+
+        Controls.Label {
+            Layout.fillWidth: true
+            wrapMode: Text.Wrap
+            text: qsTr("Oldest replays are removed when any of the limits is exceeded. Replays marked as \"Keep forever\" in the tray are never removed. Set a limit to 0 to disable it.")
+        }
+
+        GridLayout {
+            Layout.fillWidth: true
+            columns: 3
+            rowSpacing: Kirigami.Units.largeSpacing
+
+            ConfigLabel {
+                text: qsTr("Max total size:")
+            }
+
+            Controls.SpinBox {
+                id: maxSize
+                Layout.fillWidth: true
+                from: 0
+                to: 100000
+                value: Settings.retention_max_size_gb
+            }
+
+            Controls.Label {
+                text: qsTr("GB")
+            }
+
+            ConfigLabel {
+                text: qsTr("Max age:")
+            }
+
+            Controls.SpinBox {
+                id: maxAge
+                Layout.fillWidth: true
+                from: 0
+                to: 10000
+                value: Settings.retention_max_age_days
+            }
+
+            Controls.Label {
+                text: qsTr("days")
+            }
+
+            ConfigLabel {
+                text: qsTr("Max replays per app:")
+            }
+
+            Controls.SpinBox {
+                id: maxCount
+                Layout.fillWidth: true
+                from: 0
+                to: 100000
+                value: Settings.retention_max_count
+            }
+
+            Item {}
+        }
+
+        RowLayout {
+            Layout.fillWidth: true
+
+            Controls.Button {
+                Layout.fillWidth: true
+                text: qsTr("Preview")
+                icon.name: "document-preview"
+                onClicked: function () {
+                    storagePage.applyValues();
+                    storagePage.preview = Settings.retention_preview();
+                    previewLabel.visible = true;
+                }
+            }
+
+            Controls.Button {
+                Layout.fillWidth: true
+                text: qsTr("Apply")
+                icon.name: "dialog-ok-apply"
+                onClicked: function () {
+                    storagePage.applyValues();
+                    Settings.apply_retention();
+                    window.pageStack.pop();
+                }
+            }
+        }
+
+        Controls.Label {
+            id: previewLabel
+            visible: false
+            Layout.fillWidth: true
+            wrapMode: Text.Wrap
+            font.bold: true
+            text: storagePage.preview.length == 0 ? qsTr("No replays would be removed") : qsTr("%1 replays would be removed:").arg(storagePage.preview.length)
+        }
+
+        Repeater {
+            model: storagePage.preview
+
+            Controls.Label {
+                required property string modelData
+                Layout.fillWidth: true
+                elide: Text.ElideMiddle
+                text: modelData
+            }
+        }
+    }
+}
@@ -0,0 +1,59 @@
+import QtQuick
+import QtQuick.Controls
+import QtQuick.Layouts
+import org.kde.kirigami as Kirigami
+
+// Transient message for desktops without Plasma OSD or a notification service
+Window {
+    id: toast
+    property alias text: toastLabel.text
+    property alias icon: toastIcon.source
+
+    flags: Qt.Tool | Qt.FramelessWindowHint | Qt.WindowStaysOnTopHint | Qt.WindowDoesNotAcceptFocus | Qt.WindowTransparentForInput
+    color: "transparent"
+    width: toastLayout.implicitWidth + Kirigami.Units.gridUnit * 2
+    height: toastLayout.implicitHeight + Kirigami.Units.largeSpacing * 2
+    x: Screen.virtualX + (Screen.width - width) / 2
+    y: Screen.virtualY + Screen.height - height - Kirigami.Units.gridUnit * 4
+    visible: false
+
+    function popup(icon, text) {
+        toast.icon = icon;
+        toast.text = text;
+        toast.visible = true;
+        hideTimer.restart();
+    }
+
+    Timer {
+        id: hideTimer
+        interval: 2500
+        onTriggered: toast.visible = false
+    }
+
+    Rectangle {
+        anchors.fill: parent
+        radius: height / 2
+        color: Kirigami.Theme.backgroundColor
+        border.color: Kirigami.Theme.disabledTextColor
+        border.width: 1
+        opacity: 0.95
+    }
+
+    RowLayout {
+        id: toastLayout
+        anchors.centerIn: parent
+        spacing: Kirigami.Units.largeSpacing
+
+        Kirigami.Icon {
+            id: toastIcon
+            source: "dialog-information"
+            Layout.preferredHeight: Kirigami.Units.iconSizes.medium
+            Layout.preferredWidth: Kirigami.Units.iconSizes.medium
+        }
+
+        Label {
+            id: toastLabel
+            text: ""
+        }
+    }
+}
@@ -26,6 +26,11 @@ QtObject {
         objectName: "messageBox"
     }
 
+    property var toastWindow: Toast {
+        id: toast
+        objectName: "toast"
+    }
+
     property var aboutWindow: Kirigami.ApplicationWindow {
         id: aboutWindow
         objectName: "aboutWindow"